@@ -0,0 +1,363 @@
+//! `vt`: command-line front end for the SDK core.
+//!
+//! Prints the JSON result on stdout. Exit codes:
+//! - 0: `Passed`, `NewBaseline`, `Found`, or no status requested
+//! - 1: `Failed` or `NotFound`
+//! - 2: the comparison, search or locate could not run (`Error`, e.g. a missing
+//!   or undecodable image) or the arguments are invalid
+
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand};
+use vt_sdk_core as sdk;
+use sdk::filters::{Meta, Rect};
+use serde::de::DeserializeOwned;
+
+#[derive(Debug, Parser)]
+#[command(name = "vt", version, about = "Visual testing: compare, search and locate images")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compare an input image against a baseline.
+    Compare(CompareArgs),
+    /// Find a child image inside a parent image.
+    Search(SearchArgs),
+    /// Find two elements in a container and report their relative position.
+    Locate(LocateArgs),
+}
+
+#[derive(Debug, Args)]
+struct CompareArgs {
+    /// Baseline image. May be omitted with --baseline-root.
+    #[arg(long, default_value = "")]
+    baseline: String,
+    #[arg(long)]
+    input: String,
+    /// Minimum similarity (0-100) for `Passed`.
+    #[arg(long)]
+    min_similarity: Option<i32>,
+    /// Per-pixel tolerance in percent.
+    #[arg(long)]
+    noise_filter: Option<i32>,
+    /// Area to ignore as `x1,y1,x2,y2` (inclusive). Repeatable.
+    #[arg(long = "exclude", value_parser = parse_rect)]
+    excluded_areas: Vec<Rect>,
+    /// pixel, ssim or ms-ssim.
+    #[arg(long, value_parser = parse_enum::<sdk::CompareAlgorithm>)]
+    algorithm: Option<sdk::CompareAlgorithm>,
+    /// native or downscaled.
+    #[arg(long, value_parser = parse_enum::<sdk::CompareResolution>)]
+    resolution: Option<sdk::CompareResolution>,
+    /// luma, rgb or delta-e.
+    #[arg(long, value_parser = parse_enum::<sdk::ColorMode>)]
+    color_mode: Option<sdk::ColorMode>,
+    /// composite, channel or ignore-transparent.
+    #[arg(long, value_parser = parse_enum::<sdk::AlphaMode>)]
+    alpha_mode: Option<sdk::AlphaMode>,
+    /// Background for transparent pixels as `r,g,b`.
+    #[arg(long, value_parser = parse_rgb)]
+    background_color: Option<[u8; 3]>,
+    /// grayscale, overlay, side-by-side or blink.
+    #[arg(long, value_parser = parse_enum::<sdk::DiffStyle>)]
+    diff_style: Option<sdk::DiffStyle>,
+    /// Do not write a diff image.
+    #[arg(long)]
+    no_diff: bool,
+    #[arg(long)]
+    diff_output_dir: Option<String>,
+    /// File name template, e.g. `{testName}_{timestamp}`.
+    #[arg(long)]
+    diff_name_template: Option<String>,
+    /// Resolve (and record) baselines from meta under this directory.
+    #[arg(long)]
+    baseline_root: Option<String>,
+    #[command(flatten)]
+    fetch: FetchArgs,
+    #[command(flatten)]
+    meta: MetaArgs,
+}
+
+#[derive(Debug, Args)]
+struct SearchArgs {
+    #[arg(long)]
+    parent: String,
+    #[arg(long)]
+    child: String,
+    /// Smallest child scale to try, e.g. 0.5.
+    #[arg(long)]
+    min_scale: Option<f32>,
+    /// Largest child scale to try, e.g. 3.
+    #[arg(long)]
+    max_scale: Option<f32>,
+    /// Ratio between consecutive scales (default 1.1).
+    #[arg(long)]
+    scale_step: Option<f32>,
+    /// Minimum match score, 0-100 (default 90).
+    #[arg(long)]
+    min_confidence: Option<f32>,
+    /// Largest overlap (IoU, 0-1) allowed between two matches (default 0.3).
+    #[arg(long)]
+    max_overlap: Option<f32>,
+    #[command(flatten)]
+    fetch: FetchArgs,
+    #[command(flatten)]
+    meta: MetaArgs,
+}
+
+#[derive(Debug, Args)]
+struct LocateArgs {
+    #[arg(long)]
+    container: String,
+    #[arg(long)]
+    main: String,
+    #[arg(long)]
+    relative: String,
+    #[command(flatten)]
+    fetch: FetchArgs,
+    #[command(flatten)]
+    meta: MetaArgs,
+}
+
+#[derive(Debug, Args)]
+struct FetchArgs {
+    /// Timeout for downloading http(s) images, in milliseconds.
+    #[arg(long)]
+    fetch_timeout_ms: Option<u64>,
+    /// Cache downloaded images in this directory.
+    #[arg(long)]
+    fetch_cache_dir: Option<String>,
+    /// Seconds a cached download is reused (default 3600).
+    #[arg(long)]
+    fetch_cache_ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+struct MetaArgs {
+    #[arg(long)]
+    test_name: Option<String>,
+    /// `record` stores the input as the baseline.
+    #[arg(long)]
+    test_mode: Option<String>,
+    #[arg(long)]
+    project_name: Option<String>,
+    #[arg(long)]
+    execution_name: Option<String>,
+    #[arg(long)]
+    platform: Option<String>,
+    #[arg(long)]
+    locale: Option<String>,
+    #[arg(long)]
+    theme: Option<String>,
+}
+
+impl From<MetaArgs> for Meta {
+    fn from(m: MetaArgs) -> Self {
+        Meta {
+            test_name: m.test_name,
+            test_mode: m.test_mode,
+            project_name: m.project_name,
+            execution_name: m.execution_name,
+            platform: m.platform,
+            locale: m.locale,
+            theme: m.theme,
+        }
+    }
+}
+
+/// Parses `ms-ssim`, `ms_ssim` or `MsSsim` into the PascalCase serde form of `T`.
+fn parse_enum<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    let pascal: String = value
+        .split(['-', '_'])
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+        })
+        .collect();
+    serde_json::from_value(serde_json::Value::String(pascal)).map_err(|_| format!("unsupported value {value:?}"))
+}
+
+fn parse_numbers<const N: usize, T: std::str::FromStr>(value: &str) -> Result<[T; N], String> {
+    let parts: Vec<T> = value
+        .split(',')
+        .map(|p| p.trim().parse().map_err(|_| format!("invalid number in {value:?}")))
+        .collect::<Result<_, _>>()?;
+    parts.try_into().map_err(|_| format!("expected {N} comma-separated numbers, got {value:?}"))
+}
+
+fn parse_rect(value: &str) -> Result<Rect, String> {
+    let [top_left_x, top_left_y, bottom_right_x, bottom_right_y] = parse_numbers(value)?;
+    Ok(Rect { top_left_x, top_left_y, bottom_right_x, bottom_right_y })
+}
+
+fn parse_rgb(value: &str) -> Result<[u8; 3], String> {
+    parse_numbers(value)
+}
+
+impl From<CompareArgs> for sdk::CompareRequest {
+    fn from(a: CompareArgs) -> Self {
+        sdk::CompareRequest {
+            baseline_image: a.baseline,
+            input_image: a.input,
+            min_similarity: a.min_similarity,
+            noise_filter: a.noise_filter,
+            excluded_areas: (!a.excluded_areas.is_empty()).then_some(a.excluded_areas),
+            algorithm: a.algorithm,
+            resolution: a.resolution,
+            color_mode: a.color_mode,
+            alpha_mode: a.alpha_mode,
+            background_color: a.background_color,
+            diff_style: a.diff_style,
+            write_diff: a.no_diff.then_some(false),
+            diff_output_dir: a.diff_output_dir,
+            diff_name_template: a.diff_name_template,
+            baseline_root: a.baseline_root,
+            fetch_timeout_ms: a.fetch.fetch_timeout_ms,
+            fetch_cache_dir: a.fetch.fetch_cache_dir,
+            fetch_cache_ttl_secs: a.fetch.fetch_cache_ttl_secs,
+            meta: a.meta.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Runs the command, returning the JSON result and the exit code.
+fn run(command: Command) -> (String, u8) {
+    match command {
+        Command::Compare(args) => {
+            let res = sdk::compare_images(args.into());
+            let code = match res.status {
+                Some(sdk::CompareStatus::Failed) => 1,
+                Some(sdk::CompareStatus::Error) => 2,
+                _ => 0,
+            };
+            (to_json(&res), code)
+        }
+        Command::Search(args) => {
+            let res = sdk::flex_search(sdk::SearchRequest {
+                parent_image: args.parent,
+                child_image: args.child,
+                min_scale: args.min_scale,
+                max_scale: args.max_scale,
+                scale_step: args.scale_step,
+                min_confidence: args.min_confidence,
+                max_overlap: args.max_overlap,
+                fetch_timeout_ms: args.fetch.fetch_timeout_ms,
+                fetch_cache_dir: args.fetch.fetch_cache_dir,
+                fetch_cache_ttl_secs: args.fetch.fetch_cache_ttl_secs,
+                meta: args.meta.into(),
+                ..Default::default()
+            });
+            (to_json(&res), search_exit_code(&res.status))
+        }
+        Command::Locate(args) => {
+            let res = sdk::flex_locate(sdk::LocateRequest {
+                container_image: args.container,
+                main_image: args.main,
+                relative_image: args.relative,
+                fetch_timeout_ms: args.fetch.fetch_timeout_ms,
+                fetch_cache_dir: args.fetch.fetch_cache_dir,
+                fetch_cache_ttl_secs: args.fetch.fetch_cache_ttl_secs,
+                meta: args.meta.into(),
+                ..Default::default()
+            });
+            (to_json(&res), search_exit_code(&res.status))
+        }
+    }
+}
+
+fn search_exit_code(status: &str) -> u8 {
+    match status {
+        "Found" => 0,
+        "Error" => 2,
+        _ => 1,
+    }
+}
+
+fn to_json(value: &impl serde::Serialize) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| "{}".to_string())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let (json, code) = run(cli.command);
+    println!("{json}");
+    ExitCode::from(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Command {
+        Cli::try_parse_from(std::iter::once("vt").chain(args.iter().copied())).unwrap().command
+    }
+
+    #[test]
+    fn compare_flags_map_to_request() {
+        let Command::Compare(args) = parse(&[
+            "compare", "--baseline", "a.png", "--input", "b.png", "--min-similarity", "98",
+            "--exclude", "0,0,10,20", "--algorithm", "ms-ssim", "--color-mode", "delta_e",
+            "--diff-style", "SideBySide", "--background-color", "0,0,0", "--no-diff", "--platform", "ios",
+        ]) else {
+            panic!("expected compare");
+        };
+        let req: sdk::CompareRequest = args.into();
+        assert_eq!(req.baseline_image, "a.png");
+        assert_eq!(req.min_similarity, Some(98));
+        assert_eq!(req.excluded_areas.unwrap()[0].bottom_right_y, 20);
+        assert!(matches!(req.algorithm, Some(sdk::CompareAlgorithm::MsSsim)));
+        assert!(matches!(req.color_mode, Some(sdk::ColorMode::DeltaE)));
+        assert!(matches!(req.diff_style, Some(sdk::DiffStyle::SideBySide)));
+        assert_eq!(req.background_color, Some([0, 0, 0]));
+        assert_eq!(req.write_diff, Some(false));
+        assert_eq!(req.meta.platform.as_deref(), Some("ios"));
+    }
+
+    #[test]
+    fn fetch_flags_apply_to_every_command() {
+        let fetch = ["--fetch-timeout-ms", "500", "--fetch-cache-dir", "/tmp/vt", "--fetch-cache-ttl-secs", "60"];
+        let Command::Search(args) = parse(&[&["search", "--parent", "p.png", "--child", "c.png"][..], &fetch].concat()) else {
+            panic!("expected search");
+        };
+        assert_eq!(args.fetch.fetch_timeout_ms, Some(500));
+        let Command::Locate(args) = parse(&[&["locate", "--container", "c", "--main", "m", "--relative", "r"][..], &fetch].concat()) else {
+            panic!("expected locate");
+        };
+        assert_eq!(args.fetch.fetch_cache_dir.as_deref(), Some("/tmp/vt"));
+        let Command::Compare(args) = parse(&[&["compare", "--input", "b.png"][..], &fetch].concat()) else {
+            panic!("expected compare");
+        };
+        let req: sdk::CompareRequest = args.into();
+        assert_eq!(req.fetch_cache_ttl_secs, Some(60));
+        assert_eq!(search_exit_code("Error"), 2);
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        let bad = |args: &[&str]| Cli::try_parse_from(std::iter::once("vt").chain(args.iter().copied())).is_err();
+        assert!(bad(&["compare", "--input", "b.png", "--algorithm", "fuzzy"]));
+        assert!(bad(&["compare", "--input", "b.png", "--exclude", "1,2,3"]));
+        assert!(bad(&["compare", "--input", "b.png", "--background-color", "0,0,300"]));
+        assert!(bad(&["search", "--parent", "p.png"]));
+    }
+
+    #[test]
+    fn exit_codes_follow_status() {
+        let (json, code) = run(parse(&["compare", "--baseline", "missing.png", "--input", "missing.png"]));
+        assert_eq!(code, 2);
+        assert!(json.contains("BaselineMissing"));
+        let (json, code) = run(parse(&["search", "--parent", "missing.png", "--child", "missing.png"]));
+        assert_eq!(code, 2);
+        assert!(json.contains("InputMissing"));
+        let (json, code) = run(parse(&["locate", "--container", "c.png", "--main", "m.png", "--relative", "r.png"]));
+        assert_eq!(code, 2);
+        assert!(json.contains("InputMissing"));
+        let (json, code) = run(parse(&["search", "--parent", "p.png", "--child", "c.png", "--min-scale", "0"]));
+        assert_eq!(code, 2);
+        assert!(json.contains("InvalidArgument"));
+    }
+}
@@ -0,0 +1,251 @@
+//! Baseline approval workflow.
+//!
+//! A failed comparison against a `BaselineStore` parks the input as a pending
+//! change; a reviewer then approves it (the candidate replaces the baseline)
+//! or rejects it (the baseline is kept).
+//!
+//! Layout: `<root>/.pending/<id>/change.json` plus `candidate.png`. Entries
+//! stay on disk after review with their final `state`, so the queue doubles
+//! as an audit trail.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::baseline::{self, BaselineStore};
+use crate::filters::{sanitize_path_component, Meta};
+
+const PENDING_DIR: &str = ".pending";
+const CHANGE_FILE: &str = "change.json";
+const CANDIDATE_FILE: &str = "candidate.png";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeState {
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingChange {
+    pub id: String,
+    pub state: ChangeState,
+    /// Baseline the candidate would replace.
+    pub baseline_image: String,
+    /// Copy of the input that failed the comparison.
+    pub candidate_image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff_image: Option<String>,
+    pub obtained_similarity: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_similarity: Option<i32>,
+    #[serde(default)]
+    pub meta: Meta,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApprovalErrorCode {
+    NotFound,
+    /// The change was already accepted or rejected.
+    NotPending,
+    Io,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalError {
+    pub code: ApprovalErrorCode,
+    pub message: String,
+}
+
+impl ApprovalError {
+    fn io(message: String) -> Self {
+        ApprovalError { code: ApprovalErrorCode::Io, message }
+    }
+}
+
+/// What a failed comparison hands to the queue.
+pub(crate) struct Candidate<'a> {
+    pub baseline_image: &'a str,
+    pub input: &'a image::DynamicImage,
+    pub diff_image: Option<&'a str>,
+    pub obtained_similarity: f32,
+    pub min_similarity: Option<i32>,
+    pub meta: &'a Meta,
+}
+
+impl BaselineStore {
+    fn pending_dir(&self) -> PathBuf {
+        self.root().join(PENDING_DIR)
+    }
+
+    /// Changes in the queue, oldest first; `state` filters when given.
+    pub fn changes(&self, state: Option<ChangeState>) -> Result<Vec<PendingChange>, ApprovalError> {
+        let dir = self.pending_dir();
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let entries = std::fs::read_dir(&dir).map_err(|e| ApprovalError::io(format!("failed to read {}: {e}", dir.display())))?;
+        let mut out = Vec::new();
+        for entry in entries.flatten() {
+            // Skip anything that is not a readable change, e.g. a half-written entry.
+            if let Ok(change) = read_change(&entry.path()) {
+                if state.is_none_or(|s| s == change.state) {
+                    out.push(change);
+                }
+            }
+        }
+        out.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Ok(out)
+    }
+
+    pub fn change(&self, id: &str) -> Result<PendingChange, ApprovalError> {
+        let dir = self.change_dir(id)?;
+        read_change(&dir).map_err(|message| ApprovalError { code: ApprovalErrorCode::NotFound, message })
+    }
+
+    /// Promotes the candidate to the baseline.
+    pub fn approve(&self, id: &str) -> Result<PendingChange, ApprovalError> {
+        let mut change = self.pending(id)?;
+        let candidate = image::open(&change.candidate_image)
+            .map_err(|e| ApprovalError::io(format!("candidate {}: {e}", change.candidate_image)))?;
+        baseline::record(&candidate, Path::new(&change.baseline_image)).map_err(ApprovalError::io)?;
+        change.state = ChangeState::Accepted;
+        self.write(&change)?;
+        Ok(change)
+    }
+
+    /// Keeps the current baseline.
+    pub fn reject(&self, id: &str) -> Result<PendingChange, ApprovalError> {
+        let mut change = self.pending(id)?;
+        change.state = ChangeState::Rejected;
+        self.write(&change)?;
+        Ok(change)
+    }
+
+    /// Queues a candidate. An older pending change for the same baseline is
+    /// superseded, so a repeatedly failing test has one entry to review.
+    pub(crate) fn enqueue(&self, candidate: Candidate) -> Result<PendingChange, String> {
+        let previous = self.changes(Some(ChangeState::Pending)).map_err(|e| e.message)?;
+        for stale in previous.iter().filter(|c| c.baseline_image == candidate.baseline_image) {
+            let _ = std::fs::remove_dir_all(self.pending_dir().join(&stale.id));
+        }
+
+        let name = candidate.meta.test_name.as_deref().map(sanitize_path_component).unwrap_or_default();
+        let id = format!("{name}-{}", crate::compare::nano_ts());
+        let dir = self.pending_dir().join(&id);
+        let candidate_path = dir.join(CANDIDATE_FILE);
+        baseline::record(candidate.input, &candidate_path)?;
+
+        let change = PendingChange {
+            id,
+            state: ChangeState::Pending,
+            baseline_image: candidate.baseline_image.to_string(),
+            candidate_image: candidate_path.to_string_lossy().to_string(),
+            diff_image: candidate.diff_image.map(str::to_string),
+            obtained_similarity: candidate.obtained_similarity,
+            min_similarity: candidate.min_similarity,
+            meta: candidate.meta.clone(),
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        self.write(&change).map_err(|e| e.message)?;
+        Ok(change)
+    }
+
+    fn change_dir(&self, id: &str) -> Result<PathBuf, ApprovalError> {
+        // Ids are generated from sanitized components; anything else cannot exist.
+        if id.is_empty() || sanitize_path_component(id) != id {
+            return Err(ApprovalError { code: ApprovalErrorCode::NotFound, message: format!("no change with id {id:?}") });
+        }
+        Ok(self.pending_dir().join(id))
+    }
+
+    fn pending(&self, id: &str) -> Result<PendingChange, ApprovalError> {
+        let change = self.change(id)?;
+        if change.state != ChangeState::Pending {
+            return Err(ApprovalError {
+                code: ApprovalErrorCode::NotPending,
+                message: format!("change {id} is already {:?}", change.state),
+            });
+        }
+        Ok(change)
+    }
+
+    fn write(&self, change: &PendingChange) -> Result<(), ApprovalError> {
+        let path = self.change_dir(&change.id)?.join(CHANGE_FILE);
+        let json = serde_json::to_string_pretty(change).map_err(|e| ApprovalError::io(e.to_string()))?;
+        std::fs::write(&path, json).map_err(|e| ApprovalError::io(format!("failed to write {}: {e}", path.display())))
+    }
+}
+
+fn read_change(dir: &Path) -> Result<PendingChange, String> {
+    let path = dir.join(CHANGE_FILE);
+    let json = std::fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    serde_json::from_str(&json).map_err(|e| format!("invalid change {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str) -> BaselineStore {
+        BaselineStore::new(std::env::temp_dir().join(format!("vt_approval_{name}_{}", crate::compare::nano_ts())))
+    }
+
+    fn enqueue(store: &BaselineStore, baseline: &Path, value: u8) -> PendingChange {
+        let input = image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(4, 4, image::Luma([value])));
+        let meta = Meta { test_name: Some("home".into()), ..Default::default() };
+        store
+            .enqueue(Candidate {
+                baseline_image: &baseline.to_string_lossy(),
+                input: &input,
+                diff_image: None,
+                obtained_similarity: 50.0,
+                min_similarity: Some(99),
+                meta: &meta,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn approve_replaces_baseline() {
+        let store = store("approve");
+        let baseline = store.root().join("home.png");
+        let change = enqueue(&store, &baseline, 7);
+        assert_eq!(store.changes(Some(ChangeState::Pending)).unwrap().len(), 1);
+
+        let accepted = store.approve(&change.id).unwrap();
+        assert_eq!(accepted.state, ChangeState::Accepted);
+        assert_eq!(image::open(&baseline).unwrap().to_luma8().get_pixel(0, 0).0, [7]);
+        assert!(store.changes(Some(ChangeState::Pending)).unwrap().is_empty());
+        assert_eq!(store.approve(&change.id).unwrap_err().code, ApprovalErrorCode::NotPending);
+    }
+
+    #[test]
+    fn reject_keeps_baseline_and_newer_candidate_supersedes() {
+        let store = store("reject");
+        let baseline = store.root().join("home.png");
+        enqueue(&store, &baseline, 1);
+        let latest = enqueue(&store, &baseline, 2);
+        let pending = store.changes(None).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, latest.id);
+
+        assert_eq!(store.reject(&latest.id).unwrap().state, ChangeState::Rejected);
+        assert!(!baseline.exists());
+        assert_eq!(store.change(&latest.id).unwrap().state, ChangeState::Rejected);
+    }
+
+    #[test]
+    fn unknown_ids_are_not_found() {
+        let store = store("unknown");
+        assert!(store.changes(None).unwrap().is_empty());
+        assert_eq!(store.approve("missing").unwrap_err().code, ApprovalErrorCode::NotFound);
+        assert_eq!(store.reject("../escape").unwrap_err().code, ApprovalErrorCode::NotFound);
+    }
+}
@@ -0,0 +1,85 @@
+//! Baseline repository: resolves baseline images from test metadata and
+//! records new ones.
+//!
+//! Layout: `<root>/<project>/<platform>/<locale>/<theme>/<testName>.png`.
+//! Missing metadata segments fall back to `default`.
+
+use std::path::{Path, PathBuf};
+
+use crate::filters::{sanitize_path_component, Meta};
+
+const DEFAULT_SEGMENT: &str = "default";
+
+#[derive(Debug, Clone)]
+pub struct BaselineStore {
+    root: PathBuf,
+}
+
+impl BaselineStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        BaselineStore { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of the baseline for `meta`. `meta.test_name` is required.
+    pub fn resolve(&self, meta: &Meta) -> Option<PathBuf> {
+        let test_name = meta.test_name.as_deref().map(sanitize_path_component).filter(|s| !s.is_empty())?;
+        let segment = |v: &Option<String>| {
+            v.as_deref()
+                .map(sanitize_path_component)
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| DEFAULT_SEGMENT.to_string())
+        };
+        Some(
+            self.root
+                .join(segment(&meta.project_name))
+                .join(segment(&meta.platform))
+                .join(segment(&meta.locale))
+                .join(segment(&meta.theme))
+                .join(format!("{test_name}.png")),
+        )
+    }
+}
+
+/// Stores `image` as the baseline at `path`, creating parent directories.
+/// The format follows the extension of `path`.
+pub(crate) fn record(image: &image::DynamicImage, path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    }
+    image.save(path).map_err(|e| format!("failed to write baseline {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_by_meta_with_defaults() {
+        let store = BaselineStore::new("/baselines");
+        let meta = Meta {
+            test_name: Some("Login Screen".into()),
+            project_name: Some("shop".into()),
+            platform: Some("ios".into()),
+            theme: Some("dark".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            store.resolve(&meta),
+            Some(PathBuf::from("/baselines/shop/ios/default/dark/Login_Screen.png"))
+        );
+    }
+
+    #[test]
+    fn test_name_is_required_and_cannot_escape_root() {
+        let store = BaselineStore::new("/baselines");
+        assert!(store.resolve(&Meta::default()).is_none());
+        let meta = Meta { test_name: Some("../../etc/passwd".into()), ..Default::default() };
+        let path = store.resolve(&meta).unwrap();
+        assert!(path.starts_with("/baselines/default/default/default/default"), "{}", path.display());
+        assert_eq!(path.components().count(), 7);
+    }
+}
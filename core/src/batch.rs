@@ -0,0 +1,391 @@
+//! Batch comparison of two screenshot directories.
+//!
+//! Files are paired by their path relative to each directory (subdirectories
+//! included) and compared in parallel with the shared request settings.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cancel::CancelFlag;
+use crate::compare::{compare_images, CompareError, CompareErrorCode, CompareRequest, CompareResult, CompareStatus};
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "bmp", "gif"];
+const DEFAULT_NAME_TEMPLATE: &str = "vt_diff_{testName}_{timestamp}";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchRequest {
+    pub baseline_dir: String,
+    pub input_dir: String,
+    /// Settings for every pair. `baseline_image`/`input_image` are ignored and
+    /// `meta.test_name` defaults to the relative file path.
+    #[serde(default)]
+    pub compare: CompareRequest,
+    /// Worker threads; defaults to the available parallelism.
+    pub threads: Option<usize>,
+    /// Set to stop starting new pairs; the batch then ends with an error with
+    /// code `Cancelled` and lists only the pairs already compared.
+    #[serde(skip)]
+    pub cancel: CancelFlag,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResult {
+    /// `Passed` when nothing failed, errored or went missing; `Error` when a
+    /// directory could not be read or the batch was cancelled.
+    pub status: CompareStatus,
+    pub compared: u32,
+    pub passed: u32,
+    pub failed: u32,
+    pub errors: u32,
+    /// Pairs whose input was recorded as the baseline.
+    pub new_baselines: u32,
+    /// Pairs compared without a `min_similarity`, so neither passed nor failed.
+    pub unchecked: u32,
+    /// Relative paths present only in the input directory.
+    pub added: Vec<String>,
+    /// Relative paths present only in the baseline directory.
+    pub missing: Vec<String>,
+    pub results: Vec<BatchEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<crate::compare::CompareError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchEntry {
+    /// Path relative to both directories, `/`-separated.
+    pub name: String,
+    pub result: CompareResult,
+}
+
+/// Compares every file present in both directories. A pair whose comparison
+/// panics is reported as an `Internal` error rather than dropped.
+pub fn compare_batch(req: BatchRequest) -> BatchResult {
+    run_batch(req, compare_images)
+}
+
+fn run_batch(req: BatchRequest, compare: fn(CompareRequest) -> CompareResult) -> BatchResult {
+    let mut report = BatchResult {
+        status: CompareStatus::Passed,
+        compared: 0,
+        passed: 0,
+        failed: 0,
+        errors: 0,
+        new_baselines: 0,
+        unchecked: 0,
+        added: vec![],
+        missing: vec![],
+        results: vec![],
+        error: None,
+    };
+    let baseline_dir = Path::new(&req.baseline_dir);
+    let input_dir = Path::new(&req.input_dir);
+    let listing = list_images(baseline_dir, CompareErrorCode::BaselineMissing, "baseline")
+        .and_then(|b| Ok((b, list_images(input_dir, CompareErrorCode::InputMissing, "input")?)));
+    let (baselines, inputs) = match listing {
+        Ok(lists) => lists,
+        Err(err) => {
+            report.status = CompareStatus::Error;
+            report.error = Some(err);
+            return report;
+        }
+    };
+
+    let pairs: Vec<&String> = inputs.iter().filter(|n| baselines.binary_search(n).is_ok()).collect();
+    report.added = inputs.iter().filter(|n| baselines.binary_search(n).is_err()).cloned().collect();
+    report.missing = baselines.iter().filter(|n| inputs.binary_search(n).is_err()).cloned().collect();
+
+    let threads = req
+        .threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, pairs.len().max(1));
+
+    // Workers pull the next pair from a shared cursor; results land in their slot.
+    let next = AtomicUsize::new(0);
+    let mut slots: Vec<Option<CompareResult>> = vec![None; pairs.len()];
+    let done = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut out = Vec::new();
+                    while !req.cancel.is_cancelled() {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(name) = pairs.get(i) else { break };
+                        let pair = pair_request(&req, name);
+                        let result = catch_unwind(AssertUnwindSafe(|| compare(pair))).unwrap_or_else(|payload| {
+                            let message = payload
+                                .downcast_ref::<&str>()
+                                .copied()
+                                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                                .unwrap_or("panic");
+                            CompareResult::from_error(CompareErrorCode::Internal, format!("internal error: {message}"))
+                        });
+                        out.push((i, result));
+                    }
+                    out
+                })
+            })
+            .collect();
+        workers.into_iter().flat_map(|w| w.join().unwrap_or_default()).collect::<Vec<_>>()
+    });
+    for (i, result) in done {
+        slots[i] = Some(result);
+    }
+
+    let cancelled = req.cancel.is_cancelled();
+    for (name, result) in pairs.into_iter().zip(slots) {
+        if cancelled && result.is_none() {
+            continue;
+        }
+        let result = result.unwrap_or_else(|| CompareResult::from_error(CompareErrorCode::Internal, "internal error: worker stopped"));
+        report.compared += 1;
+        match result.status {
+            Some(CompareStatus::Passed) => report.passed += 1,
+            Some(CompareStatus::Failed) => report.failed += 1,
+            Some(CompareStatus::Error) => report.errors += 1,
+            Some(CompareStatus::NewBaseline) => report.new_baselines += 1,
+            None => report.unchecked += 1,
+        }
+        report.results.push(BatchEntry { name: name.clone(), result });
+    }
+    if cancelled {
+        report.status = CompareStatus::Error;
+        report.error = Some(CompareError { code: CompareErrorCode::Cancelled, message: "batch cancelled".into() });
+    } else if report.failed > 0 || report.errors > 0 || !report.missing.is_empty() {
+        report.status = CompareStatus::Failed;
+    }
+    report
+}
+
+/// The request `compare_batch` runs for the pair named `name`.
+pub(crate) fn pair_request(req: &BatchRequest, name: &str) -> CompareRequest {
+    let mut r = req.compare.clone();
+    r.cancel = req.cancel.clone();
+    r.baseline_image = Path::new(&req.baseline_dir).join(name).to_string_lossy().to_string();
+    r.input_image = Path::new(&req.input_dir).join(name).to_string_lossy().to_string();
+    r.meta.test_name.get_or_insert_with(|| name.to_string());
+    r.diff_name_template.get_or_insert_with(|| DEFAULT_NAME_TEMPLATE.to_string());
+    r
+}
+
+/// Sorted relative paths of the images under `dir`. Symlinked directories are
+/// not entered, so a link back up the tree cannot loop.
+fn list_images(dir: &Path, missing: CompareErrorCode, role: &str) -> Result<Vec<String>, crate::compare::CompareError> {
+    let error = |message: String| crate::compare::CompareError { code: missing, message };
+    if !dir.is_dir() {
+        return Err(error(format!("{role} directory not found: {}", dir.display())));
+    }
+    let mut out = Vec::new();
+    let mut pending: Vec<PathBuf> = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let entries = std::fs::read_dir(&current).map_err(|e| error(format!("failed to read {}: {e}", current.display())))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(kind) = entry.file_type() else { continue };
+            if kind.is_dir() {
+                pending.push(path);
+            } else if is_image(&path) && !(kind.is_symlink() && path.is_dir()) {
+                if let Ok(rel) = path.strip_prefix(dir) {
+                    let parts: Vec<_> = rel.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+                    out.push(parts.join("/"));
+                }
+            }
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| IMAGE_EXTENSIONS.iter().any(|x| e.eq_ignore_ascii_case(x)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let p = std::env::temp_dir().join(format!("vt_batch_{name}_{}", crate::compare::nano_ts()));
+        std::fs::create_dir_all(&p).unwrap();
+        p
+    }
+
+    fn write(dir: &Path, name: &str, value: u8) {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        image::GrayImage::from_pixel(16, 16, image::Luma([value])).save(path).unwrap();
+    }
+
+    #[test]
+    fn pairs_by_relative_path_and_aggregates() {
+        let (base, input) = (dir("base"), dir("input"));
+        for (name, b, i) in [("same.png", 10, 10), ("changed.png", 0, 255), ("nested/deep.png", 50, 50)] {
+            write(&base, name, b);
+            write(&input, name, i);
+        }
+        write(&base, "gone.png", 1);
+        write(&input, "new.png", 1);
+        std::fs::write(input.join("notes.txt"), "not an image").unwrap();
+
+        let report = compare_batch(BatchRequest {
+            baseline_dir: base.to_string_lossy().to_string(),
+            input_dir: input.to_string_lossy().to_string(),
+            compare: CompareRequest { min_similarity: Some(99), write_diff: Some(false), ..Default::default() },
+            threads: Some(2),
+            ..Default::default()
+        });
+        assert!(matches!(report.status, CompareStatus::Failed));
+        assert_eq!((report.compared, report.passed, report.failed, report.errors), (3, 2, 1, 0));
+        assert_eq!(report.added, vec!["new.png"]);
+        assert_eq!(report.missing, vec!["gone.png"]);
+        let names: Vec<_> = report.results.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["changed.png", "nested/deep.png", "same.png"]);
+        assert!(matches!(report.results[0].result.status, Some(CompareStatus::Failed)));
+    }
+
+    #[test]
+    fn identical_directories_pass() {
+        let (base, input) = (dir("ok_base"), dir("ok_input"));
+        write(&base, "a.png", 3);
+        write(&input, "a.png", 3);
+        let report = compare_batch(BatchRequest {
+            baseline_dir: base.to_string_lossy().to_string(),
+            input_dir: input.to_string_lossy().to_string(),
+            compare: CompareRequest { min_similarity: Some(100), write_diff: Some(false), ..Default::default() },
+            threads: None,
+            ..Default::default()
+        });
+        assert!(matches!(report.status, CompareStatus::Passed));
+        assert_eq!(report.passed, 1);
+    }
+
+    #[test]
+    fn panicking_pairs_are_reported_as_errors() {
+        let (base, input) = (dir("panic_base"), dir("panic_input"));
+        for name in ["a.png", "boom.png", "c.png"] {
+            write(&base, name, 5);
+            write(&input, name, 5);
+        }
+        fn compare(req: CompareRequest) -> CompareResult {
+            if req.meta.test_name.as_deref() == Some("boom.png") {
+                panic!("kaboom");
+            }
+            compare_images(req)
+        }
+        let report = run_batch(
+            BatchRequest {
+                baseline_dir: base.to_string_lossy().to_string(),
+                input_dir: input.to_string_lossy().to_string(),
+                compare: CompareRequest { min_similarity: Some(100), write_diff: Some(false), ..Default::default() },
+                threads: Some(1),
+                ..Default::default()
+            },
+            compare,
+        );
+        assert!(matches!(report.status, CompareStatus::Failed));
+        assert_eq!((report.compared, report.passed, report.failed, report.errors), (3, 2, 0, 1));
+        assert_eq!((report.new_baselines, report.unchecked), (0, 0));
+        let error = report.results[1].result.error.clone().unwrap();
+        assert_eq!(report.results[1].name, "boom.png");
+        assert_eq!(error.code, CompareErrorCode::Internal);
+        assert_eq!(error.message, "internal error: kaboom");
+    }
+
+    #[test]
+    fn cancelled_batches_stop_before_the_next_pair() {
+        let (base, input) = (dir("cancel_base"), dir("cancel_input"));
+        for name in ["a.png", "b.png", "c.png"] {
+            write(&base, name, 5);
+            write(&input, name, 5);
+        }
+        fn compare(req: CompareRequest) -> CompareResult {
+            if req.meta.test_name.as_deref() == Some("b.png") {
+                req.cancel.cancel();
+            }
+            compare_images(req)
+        }
+        let report = run_batch(
+            BatchRequest {
+                baseline_dir: base.to_string_lossy().to_string(),
+                input_dir: input.to_string_lossy().to_string(),
+                compare: CompareRequest { min_similarity: Some(100), write_diff: Some(false), ..Default::default() },
+                threads: Some(1),
+                ..Default::default()
+            },
+            compare,
+        );
+        assert!(matches!(report.status, CompareStatus::Error));
+        assert_eq!(report.error.map(|e| e.code), Some(CompareErrorCode::Cancelled));
+        assert_eq!((report.compared, report.passed, report.errors), (2, 1, 1));
+        assert_eq!(report.results[1].result.error.as_ref().map(|e| e.code), Some(CompareErrorCode::Cancelled));
+    }
+
+    #[test]
+    fn recorded_and_thresholdless_pairs_are_counted() {
+        let (base, input) = (dir("count_base"), dir("count_input"));
+        for name in ["a.png", "new.png", "plain.png"] {
+            write(&base, name, 5);
+            write(&input, name, 5);
+        }
+        fn compare(req: CompareRequest) -> CompareResult {
+            let mut result = compare_images(CompareRequest { min_similarity: None, ..req.clone() });
+            match req.meta.test_name.as_deref() {
+                Some("a.png") => result.status = Some(CompareStatus::Passed),
+                Some("new.png") => result.status = Some(CompareStatus::NewBaseline),
+                _ => {}
+            }
+            result
+        }
+        let report = run_batch(
+            BatchRequest {
+                baseline_dir: base.to_string_lossy().to_string(),
+                input_dir: input.to_string_lossy().to_string(),
+                compare: CompareRequest { write_diff: Some(false), ..Default::default() },
+                threads: Some(1),
+                ..Default::default()
+            },
+            compare,
+        );
+        assert!(matches!(report.status, CompareStatus::Passed));
+        assert_eq!((report.compared, report.passed, report.new_baselines, report.unchecked), (3, 1, 1, 1));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!((json["newBaselines"].as_u64(), json["unchecked"].as_u64()), (Some(1), Some(1)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directories_are_not_followed() {
+        let (base, input) = (dir("link_base"), dir("link_input"));
+        write(&base, "nested/a.png", 5);
+        write(&input, "nested/a.png", 5);
+        write(&base, "b.png", 5);
+        for root in [&base, &input] {
+            // A link back to the root would otherwise be walked forever.
+            std::os::unix::fs::symlink(root, root.join("nested/loop")).unwrap();
+        }
+        // Linked files are still listed.
+        std::os::unix::fs::symlink(base.join("b.png"), base.join("linked.png")).unwrap();
+        let names = list_images(&base, CompareErrorCode::BaselineMissing, "baseline").unwrap();
+        assert_eq!(names, vec!["b.png", "linked.png", "nested/a.png"]);
+        let names = list_images(&input, CompareErrorCode::InputMissing, "input").unwrap();
+        assert_eq!(names, vec!["nested/a.png"]);
+    }
+
+    #[test]
+    fn missing_directory_is_an_error() {
+        let report = compare_batch(BatchRequest {
+            baseline_dir: "/nonexistent/vt_batch".into(),
+            input_dir: "/nonexistent/vt_batch".into(),
+            ..Default::default()
+        });
+        assert!(matches!(report.status, CompareStatus::Error));
+        assert_eq!(report.error.map(|e| e.code), Some(CompareErrorCode::BaselineMissing));
+    }
+}
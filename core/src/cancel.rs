@@ -0,0 +1,25 @@
+//! Cooperative cancellation of long-running operations.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag a caller sets to stop an operation early. Clones share the
+/// flag; the default one is never set. Long loops (template matching, the
+/// scale range of a search, the pairs of a batch) check it and return early:
+/// comparisons with error code `Cancelled`, searches and locates as `NotFound`.
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
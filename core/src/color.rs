@@ -0,0 +1,124 @@
+//! Colour-space helpers: sRGB → CIELAB (D65) and the CIEDE2000 colour difference.
+
+/// A colour in CIELAB space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+/// Converts 8-bit sRGB to CIELAB under the D65 white point.
+pub(crate) fn srgb_to_lab(rgb: [u8; 3]) -> Lab {
+    let lin = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    let (r, g, b) = (lin(rgb[0]), lin(rgb[1]), lin(rgb[2]));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    Lab { l: 116.0 * fy - 16.0, a: 500.0 * (fx - fy), b: 200.0 * (fy - fz) }
+}
+
+/// CIEDE2000 colour difference with unit weighting factors (kL = kC = kH = 1).
+/// Roughly 1.0 is a just-noticeable difference; black vs white is 100.
+pub(crate) fn ciede2000(c1: Lab, c2: Lab) -> f32 {
+    use std::f32::consts::PI;
+    let deg = |r: f32| r * 180.0 / PI;
+    let rad = |d: f32| d * PI / 180.0;
+
+    let c1_ab = (c1.a * c1.a + c1.b * c1.b).sqrt();
+    let c2_ab = (c2.a * c2.a + c2.b * c2.b).sqrt();
+    let c_bar = (c1_ab + c2_ab) / 2.0;
+    let c_bar7 = c_bar.powi(7);
+    let g = 0.5 * (1.0 - (c_bar7 / (c_bar7 + 25f32.powi(7))).sqrt());
+
+    let a1 = (1.0 + g) * c1.a;
+    let a2 = (1.0 + g) * c2.a;
+    let cp1 = (a1 * a1 + c1.b * c1.b).sqrt();
+    let cp2 = (a2 * a2 + c2.b * c2.b).sqrt();
+    let hue = |b: f32, a: f32| {
+        if a == 0.0 && b == 0.0 { 0.0 } else { deg(b.atan2(a)).rem_euclid(360.0) }
+    };
+    let hp1 = hue(c1.b, a1);
+    let hp2 = hue(c2.b, a2);
+
+    let dl = c2.l - c1.l;
+    let dc = cp2 - cp1;
+    let dh = if cp1 * cp2 == 0.0 {
+        0.0
+    } else if (hp2 - hp1).abs() <= 180.0 {
+        hp2 - hp1
+    } else if hp2 - hp1 > 180.0 {
+        hp2 - hp1 - 360.0
+    } else {
+        hp2 - hp1 + 360.0
+    };
+    let d_h = 2.0 * (cp1 * cp2).sqrt() * rad(dh / 2.0).sin();
+
+    let l_bar = (c1.l + c2.l) / 2.0;
+    let cp_bar = (cp1 + cp2) / 2.0;
+    let hp_bar = if cp1 * cp2 == 0.0 {
+        hp1 + hp2
+    } else if (hp1 - hp2).abs() <= 180.0 {
+        (hp1 + hp2) / 2.0
+    } else if hp1 + hp2 < 360.0 {
+        (hp1 + hp2 + 360.0) / 2.0
+    } else {
+        (hp1 + hp2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * rad(hp_bar - 30.0).cos() + 0.24 * rad(2.0 * hp_bar).cos()
+        + 0.32 * rad(3.0 * hp_bar + 6.0).cos()
+        - 0.20 * rad(4.0 * hp_bar - 63.0).cos();
+    let d_theta = 30.0 * (-((hp_bar - 275.0) / 25.0).powi(2)).exp();
+    let cp_bar7 = cp_bar.powi(7);
+    let r_c = 2.0 * (cp_bar7 / (cp_bar7 + 25f32.powi(7))).sqrt();
+    let l50 = (l_bar - 50.0).powi(2);
+    let s_l = 1.0 + 0.015 * l50 / (20.0 + l50).sqrt();
+    let s_c = 1.0 + 0.045 * cp_bar;
+    let s_h = 1.0 + 0.015 * cp_bar * t;
+    let r_t = -(rad(2.0 * d_theta)).sin() * r_c;
+
+    let (tl, tc, th) = (dl / s_l, dc / s_c, d_h / s_h);
+    (tl * tl + tc * tc + th * th + r_t * tc * th).max(0.0).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lab(l: f32, a: f32, b: f32) -> Lab {
+        Lab { l, a, b }
+    }
+
+    #[test]
+    fn ciede2000_matches_reference_pairs() {
+        // Reference data from Sharma, Wu & Dalal (2005).
+        let cases = [
+            (lab(50.0, 2.6772, -79.7751), lab(50.0, 0.0, -82.7485), 2.0425),
+            (lab(50.0, 2.5, 0.0), lab(50.0, 0.0, -2.5), 4.3065),
+            (lab(50.0, 2.5, 0.0), lab(73.0, 25.0, -18.0), 27.1492),
+            (lab(60.2574, -34.0099, 36.2677), lab(60.4626, -34.1751, 39.4387), 1.2644),
+            (lab(22.7233, 20.0904, -46.6940), lab(23.0331, 14.9730, -42.5619), 2.0373),
+        ];
+        for (a, b, expected) in cases {
+            let got = ciede2000(a, b);
+            assert!((got - expected).abs() < 1e-3, "{a:?} vs {b:?}: {got} != {expected}");
+        }
+    }
+
+    #[test]
+    fn srgb_white_and_black() {
+        let white = srgb_to_lab([255, 255, 255]);
+        assert!((white.l - 100.0).abs() < 0.05 && white.a.abs() < 0.05 && white.b.abs() < 0.05, "{white:?}");
+        let black = srgb_to_lab([0, 0, 0]);
+        assert!(black.l.abs() < 1e-3);
+        assert!((ciede2000(white, black) - 100.0).abs() < 0.1);
+    }
+}
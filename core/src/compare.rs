@@ -1,26 +1,224 @@
+use image::Pixel;
 use serde::{Deserialize, Serialize};
 
-use crate::filters::{Meta, Rect};
+use crate::approval::Candidate;
+use crate::baseline::{self, BaselineStore};
+use crate::cancel::CancelFlag;
+use crate::color;
+use crate::filters::{sanitize_path_component, Meta, Rect};
+use crate::regions;
+use crate::render;
+use crate::source::{self, FetchSettings, ImageData, OpenError};
+use crate::ssim::{self, Plane};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CompareRequest {
+    /// Baseline path, `file://` or `http(s)://` URL. May be empty when
+    /// `baseline_root` is set.
+    #[serde(default)]
     pub baseline_image: String,
+    /// Input path, `file://` or `http(s)://` URL.
+    #[serde(default)]
     pub input_image: String,
     pub min_similarity: Option<i32>,
+    /// Per-pixel tolerance as a percentage of the 0..255 intensity range.
+    /// Differences at or below it are treated as noise and ignored by every metric.
+    /// Defaults to 1, which ignores up to 3 intensity levels.
     pub noise_filter: Option<i32>,
     pub excluded_areas: Option<Vec<Rect>>,
+    /// Metric that drives `obtained_similarity`; defaults to `Pixel`.
+    pub algorithm: Option<CompareAlgorithm>,
+    /// Working resolution; defaults to `Native`.
+    pub resolution: Option<CompareResolution>,
+    /// How two pixels are differenced; defaults to `Luma`.
+    pub color_mode: Option<ColorMode>,
+    /// How transparency is handled; defaults to `Composite`.
+    pub alpha_mode: Option<AlphaMode>,
+    /// Opaque RGB colour that transparent pixels are composited over; defaults to white.
+    pub background_color: Option<[u8; 3]>,
+    /// Rendering of the image written to `result_image_ref`; defaults to `Grayscale`.
+    pub diff_style: Option<DiffStyle>,
+    /// Set to `false` to skip writing a diff image; defaults to `true`.
+    pub write_diff: Option<bool>,
+    /// Directory for the diff image (created if missing); defaults to the system temp dir.
+    pub diff_output_dir: Option<String>,
+    /// File name for the diff image, without extension. Supports `{testName}`,
+    /// `{executionName}`, `{projectName}`, `{testMode}` and `{timestamp}`;
+    /// defaults to `vt_diff_{timestamp}`.
+    pub diff_name_template: Option<String>,
+    /// Changed areas smaller than this many baseline pixels are left out of
+    /// `diff_regions` (default 4); they still count against the similarity.
+    pub min_region_area: Option<u32>,
+    /// Root of a `BaselineStore`. When `baseline_image` is empty the baseline is
+    /// resolved from `meta`, and recorded from the input if it does not exist yet.
+    pub baseline_root: Option<String>,
+    /// Timeout for downloading remote images, in milliseconds (default 30000).
+    pub fetch_timeout_ms: Option<u64>,
+    /// Directory caching downloaded images by URL; nothing is cached when unset.
+    /// Delete it, or one entry, to invalidate the cache.
+    pub fetch_cache_dir: Option<String>,
+    /// Seconds a cached download is reused before it is fetched again (default 3600).
+    pub fetch_cache_ttl_secs: Option<u64>,
     #[serde(default)]
     pub meta: Meta,
+    /// In-memory baseline; takes precedence over `baseline_image` when set.
+    #[serde(skip)]
+    pub baseline_data: Option<ImageData>,
+    /// In-memory input; takes precedence over `input_image` when set.
+    #[serde(skip)]
+    pub input_data: Option<ImageData>,
+    /// Set to skip the comparison; the result is then an error with code `Cancelled`.
+    #[serde(skip)]
+    pub cancel: CancelFlag,
+}
+
+/// Similarity metric used for `obtained_similarity` and the pass/fail decision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareAlgorithm {
+    /// Mean absolute luminance difference.
+    #[default]
+    Pixel,
+    /// Structural similarity (single scale).
+    Ssim,
+    /// Multi-scale structural similarity.
+    MsSsim,
+}
+
+/// Resolution at which both images are compared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareResolution {
+    /// Pixel-exact comparison at the images' own size. Sizes must match.
+    #[default]
+    Native,
+    /// Fast path: both images are resized to 256×256 first. Sizes may differ,
+    /// but details thinner than a few pixels can be lost.
+    Downscaled,
+}
+
+/// Per-pixel difference used by the pixel metric, the noise filter and the diff image.
+/// SSIM and MS-SSIM always operate on luminance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorMode {
+    /// Absolute luminance difference; colour changes of equal brightness are invisible.
+    #[default]
+    Luma,
+    /// Mean absolute difference over the R, G and B channels.
+    Rgb,
+    /// Perceptual CIEDE2000 difference in Lab space.
+    DeltaE,
+}
+
+/// Treatment of transparent pixels. Colour is always compared after
+/// compositing over `background_color`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlphaMode {
+    /// Only the composited colour counts.
+    #[default]
+    Composite,
+    /// Alpha differences also count, as a channel of their own.
+    Channel,
+    /// Pixels fully transparent in both images are skipped.
+    IgnoreTransparent,
+}
+
+/// How the diff image at `CompareResult.result_image_ref` is drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiffStyle {
+    /// Absolute difference as a grayscale PNG at the working resolution.
+    #[default]
+    Grayscale,
+    /// Input screenshot at native size with changed pixels highlighted in red.
+    Overlay,
+    /// Baseline | input | overlay composite PNG.
+    SideBySide,
+    /// Animated GIF alternating baseline and input, scaled down to at most
+    /// 800 pixels per side. Still the slowest style: each frame is quantized
+    /// to 256 colours.
+    Blink,
+}
+
+/// Alpha-channel comparison reported separately from the colour metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlphaDifference {
+    /// Mean alpha similarity over included pixels, 0..100.
+    pub similarity: f32,
+    /// Included pixels whose alpha differs by more than the noise tolerance.
+    pub changed_pixels: u64,
+}
+
+/// Individual metric scores on a 0..100 scale (`delta_e` in ΔE units).
+/// Only computed metrics are present.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareMetrics {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixel: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssim: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ms_ssim: Option<f32>,
+    /// Mean CIEDE2000 difference over included pixels (`DeltaE` colour mode only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta_e: Option<f32>,
+}
+
+/// A connected area of pixels that differ beyond the noise tolerance.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffRegion {
+    /// Bounding box in baseline-image coordinates (inclusive).
+    pub rect: Rect,
+    /// Number of changed pixels, in baseline-image pixels.
+    pub area: u32,
+    /// Mean difference of the changed pixels, 0..100.
+    pub severity: f32,
+}
+
+/// Baseline and input sizes when a native comparison cannot line them up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DimensionMismatch {
+    pub baseline: (u32, u32),
+    pub input: (u32, u32),
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CompareStatus {
     Passed,
     Failed,
+    /// The comparison could not run; see `CompareResult.error`.
+    Error,
+    /// No comparison ran: the input was recorded as the baseline.
+    NewBaseline,
+}
+
+/// Machine-readable reason a comparison could not run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareErrorCode {
+    BaselineMissing,
+    InputMissing,
+    DecodeFailed,
+    DimensionMismatch,
+    BaselineWriteFailed,
+    /// A remote image could not be downloaded (network error, timeout, HTTP error status).
+    FetchFailed,
+    /// The request itself could not be used, e.g. a null or malformed FFI argument.
+    InvalidArgument,
+    /// An unexpected internal failure (a caught panic).
+    Internal,
+    /// The caller cancelled the comparison before it ran.
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompareError {
+    pub code: CompareErrorCode,
+    pub message: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CompareResult {
     pub obtained_similarity: f32,
@@ -31,74 +229,356 @@ pub struct CompareResult {
     pub noise_filter: i32,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub excluded_areas: Vec<Rect>,
+    /// Baseline path actually used (resolved from the store when applicable).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline_image: Option<String>,
+    pub algorithm: CompareAlgorithm,
+    pub resolution: CompareResolution,
+    pub color_mode: ColorMode,
+    pub metrics: CompareMetrics,
+    /// Present when either image has an alpha channel or `alpha_mode` is not `Composite`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha_difference: Option<AlphaDifference>,
+    /// Connected areas of changed pixels, largest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diff_regions: Vec<DiffRegion>,
+    /// Set when more areas changed than `diff_regions` lists; only the largest are kept.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub diff_regions_truncated: bool,
+    /// Why the diff image could not be written; the comparison itself still ran.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimension_mismatch: Option<DimensionMismatch>,
+    /// Approval queue entry created for a failed comparison against a `BaselineStore`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_change_id: Option<String>,
+    /// Why the pending change could not be queued; the status is unaffected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<CompareError>,
+}
+
+impl CompareResult {
+    /// Result for a comparison that could not run, with request defaults echoed back.
+    pub fn from_error(code: CompareErrorCode, message: impl Into<String>) -> Self {
+        let mut result = CompareResult {
+            obtained_similarity: 0.0,
+            status: None,
+            result_image_ref: None,
+            noise_filter: DEFAULT_NOISE_FILTER,
+            excluded_areas: vec![],
+            baseline_image: None,
+            algorithm: CompareAlgorithm::default(),
+            resolution: CompareResolution::default(),
+            color_mode: ColorMode::default(),
+            metrics: CompareMetrics::default(),
+            alpha_difference: None,
+            diff_regions: vec![],
+            diff_regions_truncated: false,
+            diff_error: None,
+            dimension_mismatch: None,
+            pending_change_id: None,
+            pending_error: None,
+            error: None,
+        };
+        result.set_error(code, message);
+        result
+    }
+
+    fn set_error(&mut self, code: CompareErrorCode, message: impl Into<String>) {
+        self.obtained_similarity = 0.0;
+        self.status = Some(CompareStatus::Error);
+        self.error = Some(CompareError { code, message: message.into() });
+    }
 }
 
-/// Simple placeholder implementation.
-/// Attempts to read both files and computes a byte-wise similarity ratio.
-/// - If both files are identical, returns 100.0.
-/// - If reading fails, returns 0.0.
-/// NOTE: This is not a perceptual image comparison; it's a lightweight stand‑in
-/// until a real image diff (SSIM/PSNR) is wired in.
-pub fn compare_images(req: CompareRequest) -> CompareResult {
+/// Smallest changed area, in baseline pixels, listed when `min_region_area` is unset.
+const DEFAULT_MIN_REGION_AREA: u32 = 4;
+/// Most changed areas listed in `diff_regions`.
+const MAX_DIFF_REGIONS: usize = 100;
+
+/// Applied when `noise_filter` is unset: 1% (3 intensity levels) covers
+/// encoder rounding without hiding a visible change.
+const DEFAULT_NOISE_FILTER: i32 = 1;
+
+/// Compares two images pixel by pixel.
+/// - `resolution` selects native size (default) or the 256×256 fast path.
+///   A native comparison of differently sized images reports `dimension_mismatch`.
+/// - `algorithm` selects the metric reported as `obtained_similarity`
+///   (mean luminance difference, SSIM or MS-SSIM); the pixel score is always
+///   reported in `metrics` alongside it.
+/// - `color_mode` selects luminance (default), RGB or CIEDE2000 pixel differences.
+/// - `alpha_mode` composites transparent pixels over `background_color`
+///   (default), also compares alpha, or skips fully transparent pixels.
+/// - `noise_filter` sets a per-pixel tolerance; sub-tolerance differences such as
+///   anti-aliasing or JPEG artifacts do not count against the similarity.
+/// - `diff_style` selects how the diff at `result_image_ref` is rendered;
+///   `diff_output_dir`/`diff_name_template` place it and `write_diff` turns it
+///   off. A failed write is reported in `diff_error`.
+/// - `diff_regions` lists the connected changed areas in baseline coordinates,
+///   at least `min_region_area` pixels each and at most 100, largest first;
+///   `diff_regions_truncated` tells when smaller ones were dropped.
+/// - With `baseline_root` and an empty `baseline_image`, the baseline is
+///   resolved through a `BaselineStore`. When it does not exist yet, or
+///   `meta.test_mode` is "record", the input is stored as the baseline and
+///   `status` is `NewBaseline`. A `Failed` comparison against the store, or
+///   one whose sizes differ, queues the input for approval (`pending_change_id`).
+/// - `obtained_similarity` is 0..100. Images whose compared pixels match score
+///   100.0 under every `algorithm`; `excluded_areas` are left out, and so are
+///   fully transparent pixels with `alpha_mode` `IgnoreTransparent`.
+/// - With `min_similarity`, `status` is `Passed` or `Failed`; without it,
+///   `status` is unset unless it is `NewBaseline` or `Error`.
+/// - If the comparison cannot run (missing file, failed download, undecodable
+///   image, size mismatch, cancellation), `status` is `Error`, `error` carries
+///   a code and message and the similarity is 0.0.
+pub fn compare_images(mut req: CompareRequest) -> CompareResult {
+    let settings = Settings {
+        algorithm: req.algorithm.unwrap_or_default(),
+        resolution: req.resolution.unwrap_or_default(),
+        color_mode: req.color_mode.unwrap_or_default(),
+        alpha_mode: req.alpha_mode.unwrap_or_default(),
+        background: req.background_color.unwrap_or([255, 255, 255]),
+        diff_style: req.diff_style.unwrap_or_default(),
+        noise: req.noise_filter.unwrap_or(DEFAULT_NOISE_FILTER).clamp(0, 100),
+        tolerance: noise_tolerance(req.noise_filter.unwrap_or(DEFAULT_NOISE_FILTER)),
+    };
+
+    let mut result = CompareResult {
+        obtained_similarity: 0.0,
+        status: None,
+        result_image_ref: None,
+        noise_filter: settings.noise,
+        excluded_areas: req.excluded_areas.clone().unwrap_or_default(),
+        baseline_image: None,
+        algorithm: settings.algorithm,
+        resolution: settings.resolution,
+        color_mode: settings.color_mode,
+        metrics: CompareMetrics::default(),
+        alpha_difference: None,
+        diff_regions: vec![],
+        diff_regions_truncated: false,
+        diff_error: None,
+        dimension_mismatch: None,
+        pending_change_id: None,
+        pending_error: None,
+        error: None,
+    };
+    if req.cancel.is_cancelled() {
+        result.set_error(CompareErrorCode::Cancelled, "comparison cancelled");
+        return result;
+    }
+
+    // Resolve the baseline: an explicit path wins, otherwise the store under `baseline_root`.
+    let from_store = req.baseline_data.is_none() && req.baseline_image.is_empty() && req.baseline_root.is_some();
+    if let (true, Some(root)) = (from_store, &req.baseline_root) {
+        match BaselineStore::new(root).resolve(&req.meta) {
+            Some(path) => req.baseline_image = path.to_string_lossy().to_string(),
+            None => {
+                result.set_error(CompareErrorCode::BaselineMissing, "baselineRoot is set but meta.testName is missing");
+                return result;
+            }
+        }
+    }
+    if !req.baseline_image.is_empty() {
+        result.baseline_image = Some(req.baseline_image.clone());
+    }
+
+    // Record mode, or first run against the store: the input becomes the baseline.
+    let baseline_exists = std::path::Path::new(&req.baseline_image).is_file();
+    if req.meta.is_record_mode() || (from_store && !baseline_exists) {
+        match record_baseline(&req) {
+            Ok(()) => {
+                result.obtained_similarity = 100.0;
+                result.status = Some(CompareStatus::NewBaseline);
+            }
+            Err(err) => {
+                result.status = Some(CompareStatus::Error);
+                result.error = Some(err);
+            }
+        }
+        return result;
+    }
+
     // Attempt pixel-wise comparison using `image` crate.
-    let (similarity, diff_path_opt) = match pixel_similarity(&req) {
-        Ok(pair) => pair,
-        Err(_) => {
-            // Fallback: byte-wise if loading fails
-            let sim = byte_similarity(&req.baseline_image, &req.input_image);
-            (sim, None)
+    match pixel_similarity(&req, &settings) {
+        Ok(outcome) => {
+            result.obtained_similarity = outcome.similarity;
+            result.metrics = outcome.metrics;
+            result.alpha_difference = outcome.alpha_difference;
+            result.diff_regions = outcome.diff_regions;
+            result.diff_regions_truncated = outcome.diff_regions_truncated;
+            result.result_image_ref = outcome.diff_ref;
+            result.diff_error = outcome.diff_error;
+            if let Some(min) = req.min_similarity {
+                result.status = Some(if (result.obtained_similarity as i32) >= min {
+                    CompareStatus::Passed
+                } else {
+                    CompareStatus::Failed
+                });
+            }
+            if from_store && matches!(result.status, Some(CompareStatus::Failed)) {
+                match queue_change(&req, &result) {
+                    Ok(id) => result.pending_change_id = Some(id),
+                    Err(e) => result.pending_error = Some(e),
+                }
+            }
         }
-    };
+        Err(Failure::DimensionMismatch(mismatch)) => {
+            let message = format!(
+                "baseline is {}x{} but input is {}x{}",
+                mismatch.baseline.0, mismatch.baseline.1, mismatch.input.0, mismatch.input.1
+            );
+            result.dimension_mismatch = Some(mismatch);
+            result.set_error(CompareErrorCode::DimensionMismatch, message);
+            // A resized screen is a change to review, like a failed comparison.
+            if from_store {
+                match queue_change(&req, &result) {
+                    Ok(id) => result.pending_change_id = Some(id),
+                    Err(e) => result.pending_error = Some(e),
+                }
+            }
+        }
+        Err(Failure::Error(err)) => {
+            result.status = Some(CompareStatus::Error);
+            result.error = Some(err);
+        }
+    }
 
-    let noise = req.noise_filter.unwrap_or(20).clamp(0, 100);
-    let mut status = None;
-    if let Some(min) = req.min_similarity {
-        status = Some(if (similarity as i32) >= min {
-            CompareStatus::Passed
-        } else {
-            CompareStatus::Failed
+    result
+}
+
+/// Parks the input of a failed store comparison for review.
+fn queue_change(req: &CompareRequest, result: &CompareResult) -> Result<String, String> {
+    let store = BaselineStore::new(req.baseline_root.as_deref().unwrap_or_default());
+    let input = load_input(req).map_err(|err| err.message)?;
+    let change = store.enqueue(Candidate {
+        baseline_image: &req.baseline_image,
+        input: &input,
+        diff_image: result.result_image_ref.as_deref(),
+        obtained_similarity: result.obtained_similarity,
+        min_similarity: req.min_similarity,
+        meta: &req.meta,
+    })?;
+    Ok(change.id)
+}
+
+fn record_baseline(req: &CompareRequest) -> Result<(), CompareError> {
+    if req.baseline_image.is_empty() {
+        return Err(CompareError {
+            code: CompareErrorCode::BaselineMissing,
+            message: "record mode needs baselineImage or baselineRoot".to_string(),
         });
     }
+    let Some(path) = source::local_path(&req.baseline_image) else {
+        return Err(CompareError {
+            code: CompareErrorCode::BaselineWriteFailed,
+            message: format!("cannot record a baseline to a remote URL: {}", req.baseline_image),
+        });
+    };
+    let input = load_input(req)?;
+    baseline::record(&input, &path).map_err(|message| CompareError { code: CompareErrorCode::BaselineWriteFailed, message })
+}
 
-    CompareResult {
-        obtained_similarity: similarity,
-        status,
-        result_image_ref: diff_path_opt,
-        noise_filter: noise,
-        excluded_areas: req.excluded_areas.unwrap_or_default(),
+/// Side length used by `CompareResolution::Downscaled`.
+const DOWNSCALED_SIDE: u32 = 256;
+
+/// Request options with defaults applied.
+struct Settings {
+    algorithm: CompareAlgorithm,
+    resolution: CompareResolution,
+    color_mode: ColorMode,
+    alpha_mode: AlphaMode,
+    background: [u8; 3],
+    diff_style: DiffStyle,
+    noise: i32,
+    /// Per-pixel tolerance in 0..255 units derived from `noise_filter`.
+    tolerance: u8,
+}
+
+struct Outcome {
+    similarity: f32,
+    metrics: CompareMetrics,
+    alpha_difference: Option<AlphaDifference>,
+    diff_regions: Vec<DiffRegion>,
+    diff_regions_truncated: bool,
+    diff_ref: Option<String>,
+    diff_error: Option<String>,
+}
+
+/// Why `pixel_similarity` could not produce a score.
+enum Failure {
+    Error(CompareError),
+    DimensionMismatch(DimensionMismatch),
+}
+
+impl From<CompareError> for Failure {
+    fn from(err: CompareError) -> Self {
+        Failure::Error(err)
     }
 }
 
-fn byte_similarity(a: &str, b: &str) -> f32 {
-    match (std::fs::read(a), std::fs::read(b)) {
-        (Ok(bas), Ok(inp)) => {
-            if bas == inp {
-                return 100.0;
-            }
-            let n = bas.len().min(inp.len());
-            if n == 0 { return 0.0; }
-            let same = bas.iter().zip(inp.iter()).take(n).filter(|(x, y)| x == y).count();
-            ((same as f32) / (n as f32) * 100.0).clamp(0.0, 100.0)
-        }
-        _ => 0.0,
+fn load_baseline(req: &CompareRequest) -> Result<image::DynamicImage, CompareError> {
+    load(req, req.baseline_data.as_ref(), &req.baseline_image, CompareErrorCode::BaselineMissing, "baseline")
+}
+
+fn load_input(req: &CompareRequest) -> Result<image::DynamicImage, CompareError> {
+    load(req, req.input_data.as_ref(), &req.input_image, CompareErrorCode::InputMissing, "input")
+}
+
+/// Decodes in-memory `data` if given, otherwise opens `location`, telling a
+/// missing file apart from one that cannot be downloaded or decoded.
+fn load(
+    req: &CompareRequest,
+    data: Option<&ImageData>,
+    location: &str,
+    missing: CompareErrorCode,
+    role: &str,
+) -> Result<image::DynamicImage, CompareError> {
+    if let Some(data) = data {
+        return data
+            .decode()
+            .map_err(|e| CompareError { code: CompareErrorCode::DecodeFailed, message: format!("{role} image data: {e}") });
     }
+    let settings = FetchSettings::new(req.fetch_timeout_ms, req.fetch_cache_dir.as_deref(), req.fetch_cache_ttl_secs);
+    source::open(location, &settings).map_err(|e| {
+        let (code, message) = match e {
+            OpenError::Missing => (missing, format!("{role} image not found: {location}")),
+            OpenError::Fetch(e) => (CompareErrorCode::FetchFailed, format!("{role} image {location}: {e}")),
+            OpenError::Decode(e) => (CompareErrorCode::DecodeFailed, format!("{role} image {location}: {e}")),
+        };
+        CompareError { code, message }
+    })
 }
 
-fn pixel_similarity(req: &CompareRequest) -> Result<(f32, Option<String>), String> {
-    use image::{imageops::FilterType, GenericImageView, ImageBuffer, Luma};
+fn pixel_similarity(req: &CompareRequest, settings: &Settings) -> Result<Outcome, Failure> {
+    use image::imageops::FilterType;
 
-    let img_a = image::open(&req.baseline_image).map_err(|e| format!("load A: {e}"))?;
-    let img_b = image::open(&req.input_image).map_err(|e| format!("load B: {e}"))?;
+    let img_a = load_baseline(req)?;
+    let img_b = load_input(req)?;
 
-    // Convert to grayscale to compare luminance
-    let a_gray = img_a.to_luma8();
-    let b_gray = img_b.to_luma8();
+    let has_alpha = img_a.color().has_alpha() || img_b.color().has_alpha();
+    let a_rgba = img_a.to_rgba8();
+    let b_rgba = img_b.to_rgba8();
 
-    // Normalize to the same size (square 256x256) for a robust, fast comparison
-    let target_w = 256u32;
-    let target_h = 256u32;
-    let a_res = image::imageops::resize(&a_gray, target_w, target_h, FilterType::Lanczos3);
-    let b_res = image::imageops::resize(&b_gray, target_w, target_h, FilterType::Lanczos3);
+    let (a_res, b_res) = match settings.resolution {
+        CompareResolution::Native => {
+            if a_rgba.dimensions() != b_rgba.dimensions() {
+                return Err(Failure::DimensionMismatch(DimensionMismatch {
+                    baseline: a_rgba.dimensions(),
+                    input: b_rgba.dimensions(),
+                }));
+            }
+            (a_rgba, b_rgba)
+        }
+        CompareResolution::Downscaled => (
+            // Normalize to the same size (square 256x256) for a robust, fast comparison
+            image::imageops::resize(&a_rgba, DOWNSCALED_SIDE, DOWNSCALED_SIDE, FilterType::Lanczos3),
+            image::imageops::resize(&b_rgba, DOWNSCALED_SIDE, DOWNSCALED_SIDE, FilterType::Lanczos3),
+        ),
+    };
+    let (target_w, target_h) = a_res.dimensions();
 
     // Build an exclusion mask scaled to target size
     let mut include_mask = vec![true; (target_w * target_h) as usize];
@@ -118,43 +598,186 @@ fn pixel_similarity(req: &CompareRequest) -> Result<(f32, Option<String>), Strin
             }
         }
     }
+    if settings.alpha_mode == AlphaMode::IgnoreTransparent {
+        for (keep, (pa, pb)) in include_mask.iter_mut().zip(a_res.pixels().zip(b_res.pixels())) {
+            if pa.0[3] == 0 && pb.0[3] == 0 {
+                *keep = false;
+            }
+        }
+    }
+
+    // Alpha-only report, independent of how colour is flattened below
+    let tolerance = settings.tolerance as f32;
+    let alpha_difference = (has_alpha || settings.alpha_mode != AlphaMode::Composite).then(|| {
+        let (mut sum, mut count, mut changed) = (0u64, 0u64, 0u64);
+        for ((pa, pb), &keep) in a_res.pixels().zip(b_res.pixels()).zip(&include_mask) {
+            if keep {
+                let d = pa.0[3].abs_diff(pb.0[3]);
+                sum += d as u64;
+                count += 1;
+                if d as f32 > tolerance {
+                    changed += 1;
+                }
+            }
+        }
+        let similarity = if count == 0 { 100.0 } else { (1.0 - sum as f64 / (255.0 * count as f64)) as f32 * 100.0 };
+        AlphaDifference { similarity, changed_pixels: changed }
+    });
+
+    // Flatten onto the background; colour metrics see what a viewer would see
+    let a_flat = composite(&a_res, settings.background);
+    let mut b_flat = composite(&b_res, settings.background);
+
+    // Per-pixel differences in 0..255 units. Differences within the noise
+    // tolerance snap the input pixel to the baseline so no metric sees them.
+    let mut diffs = Vec::with_capacity((target_w * target_h) as usize);
+    let alphas = a_res.pixels().zip(b_res.pixels()).map(|(pa, pb)| pa.0[3].abs_diff(pb.0[3]) as f32);
+    for ((pa, pb), alpha_d) in a_flat.pixels().zip(b_flat.pixels_mut()).zip(alphas) {
+        let mut d = pixel_difference(settings.color_mode, pa.0, pb.0);
+        if settings.alpha_mode == AlphaMode::Channel {
+            d = d.max(alpha_d);
+        }
+        if d <= tolerance {
+            *pb = *pa;
+            diffs.push(0.0);
+        } else {
+            diffs.push(d);
+        }
+    }
+    let (a_res, b_res) = (a_flat, b_flat);
 
     // Compute normalized L1 difference across included pixels
-    let mut sum_abs: u64 = 0;
+    let mut sum_abs = 0.0f64;
     let mut count: u64 = 0;
-    for (i, (pa, pb)) in a_res.pixels().zip(b_res.pixels()).enumerate() {
-        if include_mask.get(i).copied().unwrap_or(true) {
-            let da = pa.0[0] as i32;
-            let db = pb.0[0] as i32;
-            sum_abs += (da - db).unsigned_abs() as u64;
+    for (d, &keep) in diffs.iter().zip(&include_mask) {
+        if keep {
+            sum_abs += *d as f64;
             count += 1;
         }
     }
 
-    let similarity = if count == 0 {
+    let pixel = if count == 0 {
         0.0
     } else {
-        let max_total = 255u64 * count;
-        let score = 1.0 - (sum_abs as f64 / max_total as f64);
-        (score.max(0.0).min(1.0) * 100.0) as f32
+        let max_total = 255.0 * count as f64;
+        let score = 1.0 - (sum_abs / max_total);
+        (score.clamp(0.0, 1.0) * 100.0) as f32
     };
 
-    // Optionally generate a diff image (grayscale abs difference)
-    let mut diff = ImageBuffer::<Luma<u8>, Vec<u8>>::new(target_w, target_h);
-    for (i, (p, q)) in a_res.pixels().zip(b_res.pixels()).enumerate() {
-        let v = (p.0[0] as i32 - q.0[0] as i32).unsigned_abs() as u8;
-        let y = (i as u32) / target_w;
-        let x = (i as u32) % target_w;
-        diff.put_pixel(x, y, Luma([v]));
+    let mut metrics = CompareMetrics { pixel: Some(pixel), ..Default::default() };
+    if settings.color_mode == ColorMode::DeltaE && count > 0 {
+        metrics.delta_e = Some((sum_abs / count as f64 / DELTA_E_SCALE as f64) as f32);
     }
-    let out = std::env::temp_dir().join(format!("vt_diff_{}.png", nano_ts()));
-    let _ = diff.save(&out); // best effort
-    let diff_ref = out.to_string_lossy().to_string();
+    let luma = |img: &image::RgbImage| Plane::from_luma(&image::imageops::grayscale(img));
+    let similarity = match settings.algorithm {
+        CompareAlgorithm::Pixel => pixel,
+        CompareAlgorithm::Ssim => {
+            let score = ssim::ssim(&luma(&a_res), &luma(&b_res), &include_mask).clamp(0.0, 1.0) * 100.0;
+            *metrics.ssim.insert(score)
+        }
+        CompareAlgorithm::MsSsim => {
+            let score = ssim::ms_ssim(&luma(&a_res), &luma(&b_res), &include_mask) * 100.0;
+            *metrics.ms_ssim.insert(score)
+        }
+    };
+
+    // Changed regions, mapped back to baseline coordinates
+    let (sx, sy) = (img_a.width() as f64 / target_w as f64, img_a.height() as f64 / target_h as f64);
+    let min_area = req.min_region_area.unwrap_or(DEFAULT_MIN_REGION_AREA);
+    let mut diff_regions: Vec<DiffRegion> = regions::components(&diffs, &include_mask, target_w, target_h)
+        .into_iter()
+        .map(|c| DiffRegion {
+            rect: Rect {
+                top_left_x: (c.min_x as f64 * sx).floor() as u32,
+                top_left_y: (c.min_y as f64 * sy).floor() as u32,
+                bottom_right_x: (((c.max_x + 1) as f64 * sx).ceil() as u32).saturating_sub(1).min(img_a.width() - 1),
+                bottom_right_y: (((c.max_y + 1) as f64 * sy).ceil() as u32).saturating_sub(1).min(img_a.height() - 1),
+            },
+            area: (c.pixels as f64 * sx * sy).round() as u32,
+            severity: (c.total / c.pixels as f64 / 255.0 * 100.0) as f32,
+        })
+        .filter(|r| r.area >= min_area)
+        .collect();
+    let diff_regions_truncated = diff_regions.len() > MAX_DIFF_REGIONS;
+    diff_regions.truncate(MAX_DIFF_REGIONS);
 
-    Ok((similarity, Some(diff_ref)))
+    // Render the diff image in the requested style
+    let (mut diff_ref, mut diff_error) = (None, None);
+    if req.write_diff.unwrap_or(true) {
+        let map = render::DiffMap { width: target_w, height: target_h, diffs: &diffs, mask: &include_mask };
+        let out = diff_path(req, settings.diff_style);
+        let baseline_native = composite(&img_a.to_rgba8(), settings.background);
+        let input_native = composite(&img_b.to_rgba8(), settings.background);
+        let written = match out.parent() {
+            Some(dir) => std::fs::create_dir_all(dir).map_err(image::ImageError::IoError),
+            None => Ok(()),
+        }
+        .and_then(|_| render::write(settings.diff_style, &baseline_native, &input_native, &map, &out));
+        match written {
+            Ok(()) => diff_ref = Some(out.to_string_lossy().to_string()),
+            Err(e) => diff_error = Some(format!("failed to write {}: {e}", out.display())),
+        }
+    }
+
+    Ok(Outcome { similarity, metrics, alpha_difference, diff_regions, diff_regions_truncated, diff_ref, diff_error })
+}
+
+/// Resolves the diff image path from the output directory and name template.
+fn diff_path(req: &CompareRequest, style: DiffStyle) -> std::path::PathBuf {
+    let dir = req
+        .diff_output_dir
+        .as_ref()
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let template = req.diff_name_template.as_deref().unwrap_or("vt_diff_{timestamp}");
+    let meta = &req.meta;
+    let field = |v: &Option<String>| v.clone().unwrap_or_else(|| "unknown".to_string());
+    let name = template
+        .replace("{testName}", &field(&meta.test_name))
+        .replace("{executionName}", &field(&meta.execution_name))
+        .replace("{projectName}", &field(&meta.project_name))
+        .replace("{testMode}", &field(&meta.test_mode))
+        .replace("{timestamp}", &nano_ts().to_string());
+    // Keep names portable and inside `dir`.
+    dir.join(format!("{}.{}", sanitize_path_component(&name), render::extension(style)))
+}
+
+/// Alpha-blends every pixel over an opaque background colour.
+fn composite(img: &image::RgbaImage, background: [u8; 3]) -> image::RgbImage {
+    image::RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        let blend = |c: u8, bg: u8| ((c as u32 * a as u32 + bg as u32 * (255 - a as u32) + 127) / 255) as u8;
+        image::Rgb([blend(r, background[0]), blend(g, background[1]), blend(b, background[2])])
+    })
+}
+
+/// ΔE00 is mapped onto the 0..255 difference scale so that ΔE 100 (black vs white)
+/// weighs the same as a full luminance swing.
+const DELTA_E_SCALE: f32 = 2.55;
+
+/// Difference between two pixels in 0..255 units for the given colour mode.
+fn pixel_difference(mode: ColorMode, a: [u8; 3], b: [u8; 3]) -> f32 {
+    match mode {
+        ColorMode::Luma => {
+            let luma = |p: [u8; 3]| image::Rgb(p).to_luma().0[0];
+            luma(a).abs_diff(luma(b)) as f32
+        }
+        ColorMode::Rgb => a.iter().zip(&b).map(|(x, y)| x.abs_diff(*y) as f32).sum::<f32>() / 3.0,
+        ColorMode::DeltaE => {
+            if a == b {
+                return 0.0;
+            }
+            (color::ciede2000(color::srgb_to_lab(a), color::srgb_to_lab(b)) * DELTA_E_SCALE).min(255.0)
+        }
+    }
+}
+
+/// Maps a 0..100 noise filter to an absolute 8-bit intensity tolerance.
+fn noise_tolerance(noise: i32) -> u8 {
+    ((noise.clamp(0, 100) as f32) * 255.0 / 100.0).round() as u8
 }
 
-fn nano_ts() -> u128 {
+pub(crate) fn nano_ts() -> u128 {
     use std::time::{SystemTime, UNIX_EPOCH};
     SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
 }
@@ -163,7 +786,6 @@ fn nano_ts() -> u128 {
 mod tests {
     use super::*;
     use image::{ImageBuffer, Rgb};
-    use std::path::PathBuf;
 
     fn write_png(path: &str, img: &ImageBuffer<Rgb<u8>, Vec<u8>>) {
         img.save(path).expect("failed to save test png");
@@ -198,6 +820,7 @@ mod tests {
             noise_filter: None,
             excluded_areas: None,
             meta: Default::default(),
+            ..Default::default()
         };
         let res = compare_images(req);
         assert!((res.obtained_similarity - 100.0).abs() < 0.001, "got {}", res.obtained_similarity);
@@ -207,7 +830,7 @@ mod tests {
     fn different_images_lower_similarity() {
         let w = 256;
         let h = 256;
-        let mut a = solid_rgb(w, h, [255, 255, 255]);
+        let a = solid_rgb(w, h, [255, 255, 255]);
         let mut b = solid_rgb(w, h, [255, 255, 255]);
         // Put a black square in B
         for y in 80..176 { for x in 80..176 { b.put_pixel(x, y, Rgb([0, 0, 0])); } }
@@ -223,6 +846,7 @@ mod tests {
             noise_filter: None,
             excluded_areas: None,
             meta: Default::default(),
+            ..Default::default()
         };
         let res = compare_images(req);
         assert!(res.obtained_similarity < 95.0, "unexpected high similarity: {}", res.obtained_similarity);
@@ -256,6 +880,7 @@ mod tests {
             noise_filter: None,
             excluded_areas: Some(vec![rect]),
             meta: Default::default(),
+            ..Default::default()
         };
         let res = compare_images(req);
         assert!(res.obtained_similarity > 99.9, "expected near 100, got {}", res.obtained_similarity);
@@ -282,6 +907,7 @@ mod tests {
             noise_filter: None,
             excluded_areas: None,
             meta: Default::default(),
+            ..Default::default()
         };
         let res = compare_images(req);
         assert!(matches!(res.status, Some(CompareStatus::Failed)));
@@ -305,6 +931,7 @@ mod tests {
             noise_filter: None,
             excluded_areas: None,
             meta: Default::default(),
+            ..Default::default()
         };
         let res = compare_images(req);
         let path = res.result_image_ref.expect("expected diff path");
@@ -312,7 +939,7 @@ mod tests {
     }
 
     #[test]
-    fn fallback_byte_similarity_for_invalid_paths() {
+    fn missing_baseline_is_reported_as_error() {
         let req = CompareRequest {
             baseline_image: "/path/does/not/exist/a.png".into(),
             input_image: "/path/does/not/exist/b.png".into(),
@@ -320,10 +947,761 @@ mod tests {
             noise_filter: None,
             excluded_areas: None,
             meta: Default::default(),
+            ..Default::default()
         };
         let res = compare_images(req);
-        assert!(res.obtained_similarity <= 1.0);
+        assert_eq!(res.obtained_similarity, 0.0);
         assert!(res.result_image_ref.is_none());
+        assert!(matches!(res.status, Some(CompareStatus::Error)));
+        assert_eq!(res.error.map(|e| e.code), Some(CompareErrorCode::BaselineMissing));
+    }
+
+    #[test]
+    fn missing_input_and_undecodable_files_have_distinct_codes() {
+        let pa = tmp("err_a");
+        write_png(&pa, &solid_rgb(8, 8, [0, 0, 0]));
+        let missing = compare_images(CompareRequest {
+            baseline_image: pa.clone(),
+            input_image: "/path/does/not/exist/b.png".into(),
+            ..Default::default()
+        });
+        assert_eq!(missing.error.map(|e| e.code), Some(CompareErrorCode::InputMissing));
+
+        let garbage = tmp("err_garbage");
+        std::fs::write(&garbage, b"not an image").unwrap();
+        let undecodable = compare_images(CompareRequest {
+            baseline_image: pa,
+            input_image: garbage,
+            ..Default::default()
+        });
+        assert!(matches!(undecodable.status, Some(CompareStatus::Error)));
+        assert_eq!(undecodable.error.map(|e| e.code), Some(CompareErrorCode::DecodeFailed));
+
+        let json = serde_json::to_value(CompareResult::from_error(CompareErrorCode::InputMissing, "gone")).unwrap();
+        assert_eq!(json["status"], "Error");
+        assert_eq!(json["error"]["code"], "InputMissing");
+        assert_eq!(json["error"]["message"], "gone");
+    }
+
+    fn textured(w: u32, h: u32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(w, h, |x, y| {
+            let v = if ((x / 8) + (y / 8)) % 2 == 0 { 60 } else { 200 };
+            Rgb([v, v, v])
+        })
+    }
+
+    #[test]
+    fn ssim_reports_per_metric_scores() {
+        let a = textured(128, 128);
+        let pa = tmp("ssim_a");
+        let pb = tmp("ssim_b");
+        write_png(&pa, &a);
+        write_png(&pb, &a);
+
+        for algorithm in [CompareAlgorithm::Ssim, CompareAlgorithm::MsSsim] {
+            let req = CompareRequest {
+                baseline_image: pa.clone(),
+                input_image: pb.clone(),
+                algorithm: Some(algorithm),
+                ..Default::default()
+            };
+            let res = compare_images(req);
+            assert_eq!(res.algorithm, algorithm);
+            assert!((res.obtained_similarity - 100.0).abs() < 0.01, "got {}", res.obtained_similarity);
+            assert!(res.metrics.pixel.is_some());
+            let score = match algorithm {
+                CompareAlgorithm::Ssim => res.metrics.ssim,
+                _ => res.metrics.ms_ssim,
+            };
+            assert_eq!(score, Some(res.obtained_similarity));
+        }
+    }
+
+    #[test]
+    fn ssim_penalizes_lost_structure_more_than_pixel() {
+        // Blurring away the checkerboard keeps mean luminance but destroys structure.
+        let a = textured(128, 128);
+        let b = solid_rgb(128, 128, [130, 130, 130]);
+        let pa = tmp("ssim_struct_a");
+        let pb = tmp("ssim_struct_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+
+        let req = CompareRequest {
+            baseline_image: pa,
+            input_image: pb,
+            min_similarity: Some(50),
+            algorithm: Some(CompareAlgorithm::Ssim),
+            ..Default::default()
+        };
+        let res = compare_images(req);
+        let pixel = res.metrics.pixel.unwrap();
+        assert!(pixel > 70.0, "pixel {pixel}");
+        assert!(res.obtained_similarity < pixel - 40.0, "ssim {}", res.obtained_similarity);
         assert!(matches!(res.status, Some(CompareStatus::Failed)));
     }
+
+    fn jittered(w: u32, h: u32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        // +/-12 intensity speckle, like compression or anti-aliasing noise.
+        ImageBuffer::from_fn(w, h, |x, y| {
+            let v = if (x * 7 + y * 3) % 5 < 2 { 140 } else { 116 };
+            Rgb([v, v, v])
+        })
+    }
+
+    #[test]
+    fn noise_filter_tolerance_controls_pass_fail() {
+        let a = solid_rgb(128, 128, [128, 128, 128]);
+        let b = jittered(128, 128);
+        let pa = tmp("noise_a");
+        let pb = tmp("noise_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+
+        let run = |noise| {
+            compare_images(CompareRequest {
+                baseline_image: pa.clone(),
+                input_image: pb.clone(),
+                min_similarity: Some(99),
+                noise_filter: Some(noise),
+                ..Default::default()
+            })
+        };
+
+        let strict = run(0);
+        assert!(matches!(strict.status, Some(CompareStatus::Failed)), "got {}", strict.obtained_similarity);
+        let tolerant = run(10);
+        assert!(matches!(tolerant.status, Some(CompareStatus::Passed)), "got {}", tolerant.obtained_similarity);
+        assert!((tolerant.obtained_similarity - 100.0).abs() < 0.001);
+        assert_eq!(tolerant.noise_filter, 10);
+    }
+
+    #[test]
+    fn unset_noise_filter_only_ignores_faint_noise() {
+        let a = solid_rgb(64, 64, [128, 128, 128]);
+        let faint = ImageBuffer::from_fn(64, 64, |x, y| {
+            let v = if (x + y) % 2 == 0 { 130 } else { 126 };
+            Rgb([v, v, v])
+        });
+        let shifted = solid_rgb(64, 64, [168, 168, 168]);
+        let pa = tmp("default_noise_a");
+        write_png(&pa, &a);
+        let run = |name: &str, b: &ImageBuffer<Rgb<u8>, Vec<u8>>| {
+            let pb = tmp(name);
+            write_png(&pb, b);
+            compare_images(CompareRequest { baseline_image: pa.clone(), input_image: pb, min_similarity: Some(100), ..Default::default() })
+        };
+
+        let res = run("default_noise_faint", &faint);
+        assert!(matches!(res.status, Some(CompareStatus::Passed)), "got {}", res.obtained_similarity);
+        // The reported filter is the one applied.
+        assert_eq!(noise_tolerance(res.noise_filter), 3);
+        // A 40-level shift is not noise.
+        let res = run("default_noise_shift", &shifted);
+        assert!(matches!(res.status, Some(CompareStatus::Failed)), "got {}", res.obtained_similarity);
+    }
+
+    #[test]
+    fn noise_filter_does_not_hide_real_changes() {
+        let a = solid_rgb(128, 128, [255, 255, 255]);
+        let mut b = a.clone();
+        for y in 32..96 { for x in 32..96 { b.put_pixel(x, y, Rgb([0, 0, 0])); } }
+        let pa = tmp("noise_real_a");
+        let pb = tmp("noise_real_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+
+        let res = compare_images(CompareRequest {
+            baseline_image: pa,
+            input_image: pb,
+            noise_filter: Some(50),
+            algorithm: Some(CompareAlgorithm::Ssim),
+            ..Default::default()
+        });
+        assert!(res.obtained_similarity < 90.0, "got {}", res.obtained_similarity);
+    }
+
+    #[test]
+    fn noise_tolerance_scales_percentage() {
+        assert_eq!(noise_tolerance(0), 0);
+        assert_eq!(noise_tolerance(20), 51);
+        assert_eq!(noise_tolerance(100), 255);
+        assert_eq!(noise_tolerance(-5), 0);
+    }
+
+    #[test]
+    fn native_resolution_catches_one_pixel_divider() {
+        let a = solid_rgb(600, 1200, [250, 250, 250]);
+        let mut b = a.clone();
+        for x in 0..600 { b.put_pixel(x, 700, Rgb([200, 200, 200])); }
+        let pa = tmp("divider_a");
+        let pb = tmp("divider_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+
+        let run = |resolution, noise_filter| {
+            compare_images(CompareRequest {
+                baseline_image: pa.clone(),
+                input_image: pb.clone(),
+                min_similarity: Some(100),
+                noise_filter,
+                resolution: Some(resolution),
+                ..Default::default()
+            })
+        };
+
+        let native = run(CompareResolution::Native, None);
+        assert_eq!(native.resolution, CompareResolution::Native);
+        assert!(matches!(native.status, Some(CompareStatus::Failed)), "got {}", native.obtained_similarity);
+        let diff = image::open(native.result_image_ref.unwrap()).unwrap();
+        assert_eq!((diff.width(), diff.height()), (600, 1200));
+
+        // The 256x256 fast path blurs the divider into a faint band that a
+        // 10% tolerance ignores; at native size the same tolerance still catches it.
+        let fast = run(CompareResolution::Downscaled, Some(10));
+        assert!(matches!(fast.status, Some(CompareStatus::Passed)), "got {}", fast.obtained_similarity);
+        let native = run(CompareResolution::Native, Some(10));
+        assert!(matches!(native.status, Some(CompareStatus::Failed)), "got {}", native.obtained_similarity);
+    }
+
+    #[test]
+    fn native_resolution_reports_dimension_mismatch() {
+        let a = solid_rgb(100, 200, [255, 255, 255]);
+        let b = solid_rgb(100, 210, [255, 255, 255]);
+        let pa = tmp("dims_a");
+        let pb = tmp("dims_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+
+        let res = compare_images(CompareRequest {
+            baseline_image: pa.clone(),
+            input_image: pb.clone(),
+            ..Default::default()
+        });
+        assert_eq!(
+            res.dimension_mismatch,
+            Some(DimensionMismatch { baseline: (100, 200), input: (100, 210) })
+        );
+        assert_eq!(res.obtained_similarity, 0.0);
+        assert!(matches!(res.status, Some(CompareStatus::Error)));
+        assert_eq!(res.error.map(|e| e.code), Some(CompareErrorCode::DimensionMismatch));
+
+        let fast = compare_images(CompareRequest {
+            baseline_image: pa,
+            input_image: pb,
+            resolution: Some(CompareResolution::Downscaled),
+            ..Default::default()
+        });
+        assert!(fast.dimension_mismatch.is_none());
+        assert!(fast.obtained_similarity > 99.9);
+    }
+
+    #[test]
+    fn color_modes_catch_equal_luminance_change() {
+        // Red and green with (almost) the same luminance.
+        let a = solid_rgb(64, 64, [255, 0, 0]);
+        let b = solid_rgb(64, 64, [0, 76, 0]);
+        let pa = tmp("color_a");
+        let pb = tmp("color_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+
+        let run = |color_mode| {
+            compare_images(CompareRequest {
+                baseline_image: pa.clone(),
+                input_image: pb.clone(),
+                min_similarity: Some(95),
+                noise_filter: Some(0),
+                color_mode: Some(color_mode),
+                ..Default::default()
+            })
+        };
+
+        let luma = run(ColorMode::Luma);
+        assert!(matches!(luma.status, Some(CompareStatus::Passed)), "got {}", luma.obtained_similarity);
+        assert!(luma.metrics.delta_e.is_none());
+
+        let rgb = run(ColorMode::Rgb);
+        assert!(matches!(rgb.status, Some(CompareStatus::Failed)), "got {}", rgb.obtained_similarity);
+
+        let de = run(ColorMode::DeltaE);
+        assert_eq!(de.color_mode, ColorMode::DeltaE);
+        assert!(matches!(de.status, Some(CompareStatus::Failed)), "got {}", de.obtained_similarity);
+        assert!(de.metrics.delta_e.unwrap() > 50.0, "delta_e {:?}", de.metrics.delta_e);
+    }
+
+    #[test]
+    fn delta_e_noise_filter_ignores_imperceptible_shift() {
+        let a = solid_rgb(64, 64, [40, 120, 200]);
+        let b = solid_rgb(64, 64, [41, 121, 199]);
+        let pa = tmp("de_noise_a");
+        let pb = tmp("de_noise_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+
+        let res = compare_images(CompareRequest {
+            baseline_image: pa,
+            input_image: pb,
+            noise_filter: Some(1),
+            color_mode: Some(ColorMode::DeltaE),
+            ..Default::default()
+        });
+        assert!((res.obtained_similarity - 100.0).abs() < 0.001, "got {}", res.obtained_similarity);
+        assert_eq!(res.metrics.delta_e, Some(0.0));
+    }
+
+    fn write_rgba(path: &str, img: &ImageBuffer<image::Rgba<u8>, Vec<u8>>) {
+        img.save(path).expect("failed to save test png");
+    }
+
+    /// Transparent canvas with an opaque blue square; `hidden` is the RGB stored
+    /// under fully transparent pixels.
+    fn sprite(hidden: [u8; 3], square_alpha: u8) -> ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(64, 64, |x, y| {
+            if (16..48).contains(&x) && (16..48).contains(&y) {
+                image::Rgba([0, 0, 255, square_alpha])
+            } else {
+                image::Rgba([hidden[0], hidden[1], hidden[2], 0])
+            }
+        })
+    }
+
+    #[test]
+    fn alpha_composite_ignores_hidden_rgb() {
+        let pa = tmp("alpha_hidden_a");
+        let pb = tmp("alpha_hidden_b");
+        write_rgba(&pa, &sprite([0, 0, 0], 255));
+        write_rgba(&pb, &sprite([255, 0, 255], 255));
+
+        let res = compare_images(CompareRequest {
+            baseline_image: pa,
+            input_image: pb,
+            noise_filter: Some(0),
+            color_mode: Some(ColorMode::Rgb),
+            ..Default::default()
+        });
+        assert!((res.obtained_similarity - 100.0).abs() < 0.001, "got {}", res.obtained_similarity);
+        let alpha = res.alpha_difference.expect("alpha report");
+        assert_eq!(alpha.changed_pixels, 0);
+    }
+
+    #[test]
+    fn alpha_only_change_reported_separately() {
+        let pa = tmp("alpha_only_a");
+        let pb = tmp("alpha_only_b");
+        write_rgba(&pa, &sprite([0, 0, 0], 255));
+        write_rgba(&pb, &sprite([0, 0, 0], 128));
+
+        // Over a blue background the half-transparent blue square looks the same.
+        let run = |alpha_mode| {
+            compare_images(CompareRequest {
+                baseline_image: pa.clone(),
+                input_image: pb.clone(),
+                noise_filter: Some(0),
+                alpha_mode: Some(alpha_mode),
+                background_color: Some([0, 0, 255]),
+                ..Default::default()
+            })
+        };
+
+        let composite = run(AlphaMode::Composite);
+        assert!((composite.obtained_similarity - 100.0).abs() < 0.001, "got {}", composite.obtained_similarity);
+        let alpha = composite.alpha_difference.expect("alpha report");
+        assert_eq!(alpha.changed_pixels, 32 * 32);
+        assert!(alpha.similarity < 90.0);
+
+        let channel = run(AlphaMode::Channel);
+        assert!(channel.obtained_similarity < 90.0, "got {}", channel.obtained_similarity);
+    }
+
+    #[test]
+    fn ignore_transparent_skips_empty_canvas() {
+        let pa = tmp("alpha_ignore_a");
+        let pb = tmp("alpha_ignore_b");
+        write_rgba(&pa, &sprite([0, 0, 0], 255));
+        let mut b = sprite([0, 0, 0], 255);
+        for y in 16..32 { for x in 16..48 { b.put_pixel(x, y, image::Rgba([255, 255, 0, 255])); } }
+        write_rgba(&pb, &b);
+
+        let run = |alpha_mode| {
+            compare_images(CompareRequest {
+                baseline_image: pa.clone(),
+                input_image: pb.clone(),
+                noise_filter: Some(0),
+                color_mode: Some(ColorMode::Rgb),
+                alpha_mode: Some(alpha_mode),
+                ..Default::default()
+            })
+        };
+
+        // Half of the visible square changed; the transparent canvas dilutes that
+        // unless it is ignored.
+        let composite = run(AlphaMode::Composite).obtained_similarity;
+        let ignored = run(AlphaMode::IgnoreTransparent).obtained_similarity;
+        assert!(ignored < composite, "ignored {ignored} composite {composite}");
+        assert!((ignored - 50.0).abs() < 1.0, "got {ignored}");
+    }
+
+    #[test]
+    fn diff_regions_locate_changes_in_original_coordinates() {
+        let a = solid_rgb(400, 300, [255, 255, 255]);
+        let mut b = a.clone();
+        for y in 20..40 { for x in 30..80 { b.put_pixel(x, y, Rgb([0, 0, 0])); } }
+        for y in 200..205 { for x in 300..310 { b.put_pixel(x, y, Rgb([128, 128, 128])); } }
+        let pa = tmp("regions_a");
+        let pb = tmp("regions_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+
+        let res = compare_images(CompareRequest {
+            baseline_image: pa.clone(),
+            input_image: pb.clone(),
+            ..Default::default()
+        });
+        assert_eq!(res.diff_regions.len(), 2);
+        let big = res.diff_regions[0];
+        assert_eq!(
+            (big.rect.top_left_x, big.rect.top_left_y, big.rect.bottom_right_x, big.rect.bottom_right_y),
+            (30, 20, 79, 39)
+        );
+        assert_eq!(big.area, 50 * 20);
+        assert!((big.severity - 100.0).abs() < 0.01);
+        let small = res.diff_regions[1];
+        assert_eq!((small.rect.top_left_x, small.rect.top_left_y), (300, 200));
+        assert_eq!(small.area, 50);
+        assert!(small.severity < big.severity);
+
+        // The downscaled fast path still reports boxes around the same area.
+        let fast = compare_images(CompareRequest {
+            baseline_image: pa,
+            input_image: pb,
+            resolution: Some(CompareResolution::Downscaled),
+            ..Default::default()
+        });
+        let r = fast.diff_regions[0].rect;
+        assert!(r.top_left_x <= 30 && r.bottom_right_x >= 79, "{r:?}");
+        assert!(r.top_left_y <= 20 && r.bottom_right_y >= 39, "{r:?}");
+    }
+
+    #[test]
+    fn noisy_diffs_list_a_bounded_number_of_regions() {
+        // Isolated speckles every 4 pixels, and one real 20x20 change.
+        let a = solid_rgb(200, 200, [255, 255, 255]);
+        let mut b = a.clone();
+        for y in (0..200).step_by(4) { for x in (0..200).step_by(4) { b.put_pixel(x, y, Rgb([0, 0, 0])); } }
+        let mut changed = b.clone();
+        for y in 101..121 { for x in 101..121 { changed.put_pixel(x, y, Rgb([0, 0, 0])); } }
+        let (pa, pb, pc) = (tmp("noisy_a"), tmp("noisy_b"), tmp("noisy_c"));
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+        write_png(&pc, &changed);
+        let run = |input: &str, min_region_area| {
+            compare_images(CompareRequest {
+                baseline_image: pa.clone(),
+                input_image: input.to_string(),
+                write_diff: Some(false),
+                min_region_area,
+                ..Default::default()
+            })
+        };
+
+        let speckles = run(&pb, None);
+        assert!(speckles.obtained_similarity < 100.0);
+        assert!(speckles.diff_regions.is_empty());
+        assert!(!speckles.diff_regions_truncated);
+
+        let res = run(&pc, None);
+        assert_eq!(res.diff_regions.len(), 1);
+        let r = res.diff_regions[0].rect;
+        assert_eq!((r.top_left_x, r.top_left_y, r.bottom_right_x, r.bottom_right_y), (100, 100, 120, 120));
+
+        // Listing every speckle stops at the cap.
+        let all = run(&pb, Some(1));
+        assert_eq!(all.diff_regions.len(), MAX_DIFF_REGIONS);
+        assert!(all.diff_regions_truncated);
+        assert_eq!(serde_json::to_value(&all).unwrap()["diffRegionsTruncated"], true);
+        assert!(serde_json::to_value(&res).unwrap().get("diffRegionsTruncated").is_none());
+    }
+
+    #[test]
+    fn identical_and_excluded_changes_have_no_regions() {
+        let a = solid_rgb(64, 64, [255, 255, 255]);
+        let mut b = a.clone();
+        for y in 10..20 { for x in 10..20 { b.put_pixel(x, y, Rgb([0, 0, 0])); } }
+        let pa = tmp("regions_ex_a");
+        let pb = tmp("regions_ex_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+
+        let rect = Rect { top_left_x: 10, top_left_y: 10, bottom_right_x: 19, bottom_right_y: 19 };
+        let res = compare_images(CompareRequest {
+            baseline_image: pa,
+            input_image: pb,
+            excluded_areas: Some(vec![rect]),
+            ..Default::default()
+        });
+        assert!(res.diff_regions.is_empty());
+    }
+
+    #[test]
+    fn diff_styles_write_expected_images() {
+        let a = solid_rgb(40, 30, [255, 255, 255]);
+        let mut b = a.clone();
+        for y in 10..20 { for x in 10..20 { b.put_pixel(x, y, Rgb([0, 0, 255])); } }
+        let pa = tmp("style_a");
+        let pb = tmp("style_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+
+        let run = |diff_style| {
+            let res = compare_images(CompareRequest {
+                baseline_image: pa.clone(),
+                input_image: pb.clone(),
+                diff_style: Some(diff_style),
+                ..Default::default()
+            });
+            res.result_image_ref.expect("diff path")
+        };
+
+        let overlay = image::open(run(DiffStyle::Overlay)).unwrap().to_rgb8();
+        assert_eq!(overlay.dimensions(), (40, 30));
+        let changed = overlay.get_pixel(15, 15).0;
+        let unchanged = overlay.get_pixel(2, 2).0;
+        assert!(changed[0] > 150 && changed[1] < 50, "changed {changed:?}");
+        assert_eq!(unchanged, [255, 255, 255]);
+
+        let side = image::open(run(DiffStyle::SideBySide)).unwrap();
+        assert_eq!((side.width(), side.height()), (40 * 3 + 2 * 8, 30));
+
+        let blink = run(DiffStyle::Blink);
+        assert!(blink.ends_with(".gif"), "{blink}");
+        let decoder = image::codecs::gif::GifDecoder::new(std::fs::File::open(&blink).unwrap()).unwrap();
+        use image::AnimationDecoder;
+        assert_eq!(decoder.into_frames().count(), 2);
+    }
+
+    #[test]
+    fn blink_frames_of_large_screenshots_are_scaled_down() {
+        let a = solid_rgb(1600, 400, [255, 255, 255]);
+        let mut b = a.clone();
+        for y in 100..300 { for x in 100..300 { b.put_pixel(x, y, Rgb([0, 0, 0])); } }
+        let (pa, pb) = (tmp("blink_large_a"), tmp("blink_large_b"));
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+        let res = compare_images(CompareRequest {
+            baseline_image: pa,
+            input_image: pb,
+            diff_style: Some(DiffStyle::Blink),
+            ..Default::default()
+        });
+        let gif = res.result_image_ref.expect("diff path");
+        use image::AnimationDecoder;
+        let decoder = image::codecs::gif::GifDecoder::new(std::fs::File::open(&gif).unwrap()).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].buffer().dimensions(), (800, 200));
+        // The change is still visible at half size.
+        assert!(frames[1].buffer().get_pixel(100, 100).0[0] < 50);
+        assert!(frames[0].buffer().get_pixel(100, 100).0[0] > 200);
+    }
+
+    #[test]
+    fn diff_output_dir_and_template_are_deterministic() {
+        let a = solid_rgb(16, 16, [255, 255, 255]);
+        let b = solid_rgb(16, 16, [0, 0, 0]);
+        let pa = tmp("naming_a");
+        let pb = tmp("naming_b");
+        write_png(&pa, &a);
+        write_png(&pb, &b);
+        let dir = std::env::temp_dir().join(format!("vt_out_{}", nano_ts())).join("nested");
+
+        let req = CompareRequest {
+            baseline_image: pa,
+            input_image: pb,
+            diff_output_dir: Some(dir.to_string_lossy().to_string()),
+            diff_name_template: Some("{executionName}-{testName}".into()),
+            meta: Meta {
+                test_name: Some("login/screen".into()),
+                execution_name: Some("run1".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let res = compare_images(req.clone());
+        let expected = dir.join("run1-login_screen.png");
+        assert_eq!(res.result_image_ref.as_deref(), Some(expected.to_string_lossy().as_ref()));
+        assert!(expected.exists());
+        assert!(res.diff_error.is_none());
+
+        let skipped = compare_images(CompareRequest { write_diff: Some(false), ..req });
+        assert!(skipped.result_image_ref.is_none());
+        assert!(skipped.diff_error.is_none());
+    }
+
+    #[test]
+    fn diff_save_failure_is_reported() {
+        let a = solid_rgb(16, 16, [255, 255, 255]);
+        let pa = tmp("savefail_a");
+        write_png(&pa, &a);
+        // A regular file where the output directory should be.
+        let blocker = tmp("savefail_blocker");
+        std::fs::write(&blocker, b"x").unwrap();
+
+        let res = compare_images(CompareRequest {
+            baseline_image: pa.clone(),
+            input_image: pa,
+            min_similarity: Some(99),
+            diff_output_dir: Some(format!("{blocker}/sub")),
+            ..Default::default()
+        });
+        assert!(matches!(res.status, Some(CompareStatus::Passed)));
+        assert!(res.result_image_ref.is_none());
+        assert!(res.diff_error.is_some());
+    }
+
+    #[test]
+    fn baseline_store_records_then_compares() {
+        let root = std::env::temp_dir().join(format!("vt_store_{}", nano_ts()));
+        let pi = tmp("store_input");
+        write_png(&pi, &solid_rgb(32, 32, [10, 200, 10]));
+        let meta = Meta {
+            test_name: Some("home".into()),
+            platform: Some("android".into()),
+            ..Default::default()
+        };
+        let req = CompareRequest {
+            input_image: pi.clone(),
+            min_similarity: Some(99),
+            baseline_root: Some(root.to_string_lossy().to_string()),
+            meta: meta.clone(),
+            ..Default::default()
+        };
+
+        let first = compare_images(req.clone());
+        assert!(matches!(first.status, Some(CompareStatus::NewBaseline)));
+        let stored = root.join("default/android/default/default/home.png");
+        assert_eq!(first.baseline_image.as_deref(), Some(stored.to_string_lossy().as_ref()));
+        assert!(stored.is_file());
+
+        let second = compare_images(req.clone());
+        assert!(matches!(second.status, Some(CompareStatus::Passed)));
+
+        // A changed input fails, unless test_mode asks to re-record.
+        let pj = tmp("store_input_changed");
+        write_png(&pj, &solid_rgb(32, 32, [200, 10, 10]));
+        let changed = CompareRequest { input_image: pj, ..req };
+        let failed = compare_images(changed.clone());
+        assert!(matches!(failed.status, Some(CompareStatus::Failed)));
+        let id = failed.pending_change_id.expect("failed store comparison is queued");
+        let store = BaselineStore::new(&root);
+        assert_eq!(store.change(&id).unwrap().baseline_image, stored.to_string_lossy());
+        let recorded = compare_images(CompareRequest {
+            meta: Meta { test_mode: Some("record".into()), ..meta },
+            ..changed.clone()
+        });
+        assert!(matches!(recorded.status, Some(CompareStatus::NewBaseline)));
+        assert!(matches!(compare_images(changed).status, Some(CompareStatus::Passed)));
+    }
+
+    #[test]
+    fn approving_a_pending_change_makes_the_next_run_pass() {
+        let root = std::env::temp_dir().join(format!("vt_store_approve_{}", nano_ts()));
+        let (pa, pb) = (tmp("approve_a"), tmp("approve_b"));
+        write_png(&pa, &solid_rgb(16, 16, [0, 0, 0]));
+        write_png(&pb, &solid_rgb(16, 16, [255, 255, 255]));
+        let req = |input: &str| CompareRequest {
+            input_image: input.to_string(),
+            min_similarity: Some(99),
+            baseline_root: Some(root.to_string_lossy().to_string()),
+            meta: Meta { test_name: Some("settings".into()), ..Default::default() },
+            write_diff: Some(false),
+            ..Default::default()
+        };
+        assert!(matches!(compare_images(req(&pa)).status, Some(CompareStatus::NewBaseline)));
+        let failed = compare_images(req(&pb));
+        let id = failed.pending_change_id.unwrap();
+
+        BaselineStore::new(&root).approve(&id).unwrap();
+        assert!(matches!(compare_images(req(&pb)).status, Some(CompareStatus::Passed)));
+    }
+
+    #[test]
+    fn resized_store_screens_are_queued_for_approval() {
+        let root = std::env::temp_dir().join(format!("vt_store_resized_{}", nano_ts()));
+        let (pa, pb) = (tmp("resized_a"), tmp("resized_b"));
+        write_png(&pa, &solid_rgb(16, 16, [40, 40, 40]));
+        write_png(&pb, &solid_rgb(16, 20, [40, 40, 40]));
+        let req = |input: &str| CompareRequest {
+            input_image: input.to_string(),
+            min_similarity: Some(99),
+            baseline_root: Some(root.to_string_lossy().to_string()),
+            meta: Meta { test_name: Some("resized".into()), ..Default::default() },
+            write_diff: Some(false),
+            ..Default::default()
+        };
+        assert!(matches!(compare_images(req(&pa)).status, Some(CompareStatus::NewBaseline)));
+        let mismatched = compare_images(req(&pb));
+        assert!(matches!(mismatched.status, Some(CompareStatus::Error)));
+        assert_eq!(mismatched.error.map(|e| e.code), Some(CompareErrorCode::DimensionMismatch));
+        let id = mismatched.pending_change_id.expect("resized store comparison is queued");
+
+        BaselineStore::new(&root).approve(&id).unwrap();
+        assert!(matches!(compare_images(req(&pb)).status, Some(CompareStatus::Passed)));
+    }
+
+    #[test]
+    fn baseline_store_needs_test_name() {
+        let res = compare_images(CompareRequest {
+            input_image: "in.png".into(),
+            baseline_root: Some("/tmp".into()),
+            ..Default::default()
+        });
+        assert!(matches!(res.status, Some(CompareStatus::Error)));
+        assert_eq!(res.error.map(|e| e.code), Some(CompareErrorCode::BaselineMissing));
+    }
+
+    #[test]
+    fn in_memory_inputs_skip_the_file_system() {
+        let img = textured(24, 16);
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(img.clone())
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        // RGBA rows padded to 100 bytes.
+        let mut pixels = vec![0u8; 100 * 16];
+        for (x, y, p) in img.enumerate_pixels() {
+            let i = (y * 100 + x * 4) as usize;
+            pixels[i..i + 4].copy_from_slice(&[p[0], p[1], p[2], 255]);
+        }
+        let res = compare_images(CompareRequest {
+            baseline_data: Some(ImageData::Encoded(png)),
+            input_data: Some(ImageData::Rgba { width: 24, height: 16, stride: 100, pixels }),
+            min_similarity: Some(100),
+            write_diff: Some(false),
+            ..Default::default()
+        });
+        assert!(matches!(res.status, Some(CompareStatus::Passed)), "{:?}", res.error);
+        assert!(res.baseline_image.is_none());
+
+        let bad = compare_images(CompareRequest {
+            baseline_data: Some(ImageData::Encoded(vec![1, 2, 3])),
+            input_image: "unused.png".into(),
+            ..Default::default()
+        });
+        assert_eq!(bad.error.map(|e| e.code), Some(CompareErrorCode::DecodeFailed));
+    }
+
+    #[test]
+    fn in_memory_input_records_into_the_store() {
+        let root = std::env::temp_dir().join(format!("vt_store_mem_{}", nano_ts()));
+        let req = CompareRequest {
+            input_data: Some(ImageData::Rgba { width: 2, height: 2, stride: 8, pixels: vec![200; 16] }),
+            baseline_root: Some(root.to_string_lossy().to_string()),
+            meta: Meta { test_name: Some("mem".into()), ..Default::default() },
+            min_similarity: Some(100),
+            ..Default::default()
+        };
+        assert!(matches!(compare_images(req.clone()).status, Some(CompareStatus::NewBaseline)));
+        assert!(matches!(compare_images(req).status, Some(CompareStatus::Passed)));
+    }
 }
@@ -16,4 +16,24 @@ pub struct Meta {
     pub test_mode: Option<String>,
     pub project_name: Option<String>,
     pub execution_name: Option<String>,
+    pub platform: Option<String>,
+    pub locale: Option<String>,
+    pub theme: Option<String>,
+}
+
+impl Meta {
+    /// Whether `test_mode` asks to (re)record baselines instead of comparing.
+    pub fn is_record_mode(&self) -> bool {
+        self.test_mode.as_deref().is_some_and(|m| m.eq_ignore_ascii_case("record"))
+    }
+}
+
+/// Replaces anything but ASCII alphanumerics, `-`, `_` and `.` with `_`, and
+/// strips leading dots, so a value can be used as a single path component.
+pub(crate) fn sanitize_path_component(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    cleaned.trim_start_matches('.').to_string()
 }
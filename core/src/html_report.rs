@@ -0,0 +1,341 @@
+//! Self-contained HTML visual-regression report.
+//!
+//! Images are embedded as data URIs so the file can be archived or attached
+//! to a CI run on its own. Images are loaded like the comparison loaded them:
+//! from in-memory data, local paths or remote URLs. Excluded areas are
+//! outlined in blue over the baseline and input, and diff regions, which are
+//! in baseline coordinates, in red over the baseline.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::batch::{pair_request, BatchRequest, BatchResult};
+use crate::compare::{CompareRequest, CompareResult, CompareStatus};
+use crate::filters::Rect;
+use crate::source::{self, FetchSettings, ImageData};
+
+/// One comparison in the report: what was asked and what came back.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReportEntry {
+    pub request: CompareRequest,
+    pub result: CompareResult,
+}
+
+impl ReportEntry {
+    /// Entries for every pair of a batch run.
+    pub fn from_batch(req: &BatchRequest, batch: &BatchResult) -> Vec<ReportEntry> {
+        batch
+            .results
+            .iter()
+            .map(|e| ReportEntry { request: pair_request(req, &e.name), result: e.result.clone() })
+            .collect()
+    }
+
+    fn name(&self) -> String {
+        self.request.meta.test_name.clone().unwrap_or_else(|| {
+            Path::new(&self.request.input_image)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default()
+        })
+    }
+}
+
+const STYLE: &str = "\
+body{font-family:-apple-system,Segoe UI,Roboto,sans-serif;margin:24px;color:#222}\
+h1{margin:0 0 4px}.summary{margin:0 0 16px;color:#555}\
+.filters button{margin-right:6px;padding:4px 12px;border:1px solid #aaa;background:#fff;border-radius:4px;cursor:pointer}\
+.filters button.active{background:#222;color:#fff}\
+.entry{border:1px solid #ddd;border-radius:6px;margin:16px 0;padding:12px}\
+.entry h2{font-size:16px;margin:0 0 8px}.badge{padding:2px 8px;border-radius:4px;color:#fff;font-size:12px;margin-left:8px}\
+.Passed{background:#2e7d32}.Failed{background:#c62828}.Error{background:#6a1b9a}.NewBaseline{background:#1565c0}.Unknown{background:#777}\
+table{border-collapse:collapse;font-size:13px;margin-bottom:8px}td{padding:2px 12px 2px 0;vertical-align:top}td:first-child{color:#777}\
+.images{display:flex;gap:12px;flex-wrap:wrap}figure{margin:0;max-width:32%}figcaption{font-size:12px;color:#777}\
+.frame{position:relative;display:inline-block}.frame img{display:block;max-width:100%}\
+.box{position:absolute;box-sizing:border-box}.excluded{border:2px dashed #1565c0;background:rgba(21,101,192,.12)}\
+.changed{border:2px solid #c62828}.missing{padding:24px;color:#999;border:1px dashed #ccc}";
+
+const SCRIPT: &str = "\
+function vtFilter(s){document.querySelectorAll('.entry').forEach(function(e){\
+e.style.display=(s==='all'||e.dataset.status===s)?'':'none'});\
+document.querySelectorAll('.filters button').forEach(function(b){b.classList.toggle('active',b.dataset.filter===s)})}";
+
+/// Renders the report as a single HTML document.
+pub fn render(title: &str, entries: &[ReportEntry]) -> String {
+    let count = |s: CompareStatus| entries.iter().filter(|e| e.result.status == Some(s)).count();
+    let (passed, failed) = (count(CompareStatus::Passed), count(CompareStatus::Failed));
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{t}</title><style>{STYLE}</style>\
+         <script>{SCRIPT}</script></head><body><h1>{t}</h1>\
+         <p class=\"summary\">{total} comparisons: {passed} passed, {failed} failed, {other} other</p>\
+         <div class=\"filters\"><button class=\"active\" data-filter=\"all\" onclick=\"vtFilter('all')\">All</button>\
+         <button data-filter=\"Failed\" onclick=\"vtFilter('Failed')\">Failed</button>\
+         <button data-filter=\"Passed\" onclick=\"vtFilter('Passed')\">Passed</button></div>",
+        t = escape(title),
+        total = entries.len(),
+        other = entries.len() - passed - failed,
+    );
+    for entry in entries {
+        entry_html(&mut html, entry);
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+/// Renders the report and writes it to `path`, creating parent directories.
+pub fn write(path: impl AsRef<Path>, title: &str, entries: &[ReportEntry]) -> std::io::Result<()> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, render(title, entries))
+}
+
+fn entry_html(html: &mut String, entry: &ReportEntry) {
+    let (req, res) = (&entry.request, &entry.result);
+    let status = res.status.map(|s| format!("{s:?}")).unwrap_or_else(|| "Unknown".to_string());
+    let _ = write!(
+        html,
+        "<section class=\"entry\" data-status=\"{status}\"><h2>{}<span class=\"badge {status}\">{status}</span></h2><table>",
+        escape(&entry.name())
+    );
+    let mut row = |label: &str, value: String| {
+        let _ = write!(html, "<tr><td>{label}</td><td>{}</td></tr>", escape(&value));
+    };
+    row("Similarity", format!("{:.2}", res.obtained_similarity));
+    if let Some(min) = req.min_similarity {
+        row("Threshold", min.to_string());
+    }
+    row("Algorithm", format!("{:?}", res.algorithm));
+    row("Noise filter", format!("{}%", res.noise_filter));
+    let meta = &req.meta;
+    for (label, value) in [
+        ("Test", &meta.test_name),
+        ("Mode", &meta.test_mode),
+        ("Project", &meta.project_name),
+        ("Execution", &meta.execution_name),
+        ("Platform", &meta.platform),
+        ("Locale", &meta.locale),
+        ("Theme", &meta.theme),
+    ] {
+        if let Some(value) = value {
+            row(label, value.clone());
+        }
+    }
+    if let Some(err) = &res.error {
+        row("Error", format!("{:?}: {}", err.code, err.message));
+    }
+    html.push_str("</table><div class=\"images\">");
+
+    let excluded: &[Rect] = if res.excluded_areas.is_empty() {
+        req.excluded_areas.as_deref().unwrap_or_default()
+    } else {
+        &res.excluded_areas
+    };
+    let changed: Vec<Rect> = res.diff_regions.iter().map(|r| r.rect).collect();
+    // A baseline resolved from a store is a file, even if the request held data.
+    let (baseline, baseline_data) = match &res.baseline_image {
+        Some(path) => (path.clone(), None),
+        None => (req.baseline_image.clone(), req.baseline_data.as_ref()),
+    };
+    let shown = |data: Option<&ImageData>, location: &str| if data.is_some() { "in memory".to_string() } else { location.to_string() };
+    let input_data = req.input_data.as_ref();
+    let boxes = [(excluded, "excluded"), (changed.as_slice(), "changed")];
+    figure(html, "Baseline", &shown(baseline_data, &baseline), embed(req, baseline_data, &baseline), &boxes);
+    figure(html, "Input", &shown(input_data, &req.input_image), embed(req, input_data, &req.input_image), &boxes[..1]);
+    if let Some(diff) = &res.result_image_ref {
+        figure(html, "Diff", diff, embed(req, None, diff), &[]);
+    }
+    html.push_str("</div></section>");
+}
+
+/// Encoded image bytes ready for a data URI.
+struct Embedded {
+    bytes: Vec<u8>,
+    mime: &'static str,
+    width: u32,
+    height: u32,
+}
+
+/// Loads `data`, or else `location` with the request's fetch settings.
+/// Local files are embedded as they are, which keeps animated diffs moving;
+/// anything else is re-encoded as PNG.
+fn embed(req: &CompareRequest, data: Option<&ImageData>, location: &str) -> Option<Embedded> {
+    let img = match data {
+        Some(data) => data.decode().ok()?,
+        None if source::is_remote(location) => {
+            let settings = FetchSettings::new(req.fetch_timeout_ms, req.fetch_cache_dir.as_deref(), req.fetch_cache_ttl_secs);
+            source::open(location, &settings).ok()?
+        }
+        None => {
+            let path = source::local_path(location)?;
+            let (width, height) = image::image_dimensions(&path).ok()?;
+            let bytes = std::fs::read(&path).ok()?;
+            return Some(Embedded { bytes, mime: mime(location), width, height });
+        }
+    };
+    let mut bytes = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageOutputFormat::Png).ok()?;
+    Some(Embedded { bytes, mime: "image/png", width: img.width(), height: img.height() })
+}
+
+/// An embedded image with boxes positioned in percent of its natural size.
+fn figure(html: &mut String, caption: &str, location: &str, image: Option<Embedded>, boxes: &[(&[Rect], &str)]) {
+    let _ = write!(html, "<figure>");
+    match image {
+        Some(Embedded { bytes, mime, width: w, height: h }) => {
+            let _ = write!(html, "<div class=\"frame\"><img src=\"data:{mime};base64,{}\" alt=\"{}\">", base64(&bytes), escape(caption));
+            for (rects, class) in boxes {
+                for r in rects.iter() {
+                    let pct = |v: u32, of: u32| v as f32 * 100.0 / of.max(1) as f32;
+                    let _ = write!(
+                        html,
+                        "<div class=\"box {class}\" style=\"left:{:.3}%;top:{:.3}%;width:{:.3}%;height:{:.3}%\"></div>",
+                        pct(r.top_left_x, w),
+                        pct(r.top_left_y, h),
+                        pct(r.bottom_right_x.saturating_sub(r.top_left_x) + 1, w),
+                        pct(r.bottom_right_y.saturating_sub(r.top_left_y) + 1, h),
+                    );
+                }
+            }
+            html.push_str("</div>");
+        }
+        None => {
+            let _ = write!(html, "<div class=\"missing\">not available</div>");
+        }
+    }
+    let _ = write!(html, "<figcaption>{} &mdash; {}</figcaption></figure>", escape(caption), escape(location));
+}
+
+fn mime(path: &str) -> &'static str {
+    let ext = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+    match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "bmp" => "image/bmp",
+        _ => "image/png",
+    }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Standard padded base64.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::compare_images;
+    use crate::filters::Meta;
+
+    #[test]
+    fn base64_matches_rfc4648_vectors() {
+        let cases = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foobar", "Zm9vYmFy")];
+        for (input, expected) in cases {
+            assert_eq!(base64(input.as_bytes()), expected);
+        }
+    }
+
+    #[test]
+    fn report_embeds_images_boxes_and_meta() {
+        let dir = std::env::temp_dir().join(format!("vt_html_{}", crate::compare::nano_ts()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.png"), dir.join("b.png"));
+        image::GrayImage::from_pixel(20, 10, image::Luma([0])).save(&a).unwrap();
+        image::GrayImage::from_fn(20, 10, |x, _| image::Luma([if x >= 10 { 255 } else { 0 }])).save(&b).unwrap();
+        let request = CompareRequest {
+            baseline_image: a.to_string_lossy().to_string(),
+            input_image: b.to_string_lossy().to_string(),
+            min_similarity: Some(99),
+            excluded_areas: Some(vec![Rect { top_left_x: 0, top_left_y: 0, bottom_right_x: 4, bottom_right_y: 4 }]),
+            diff_output_dir: Some(dir.to_string_lossy().to_string()),
+            meta: Meta { test_name: Some("<login>".into()), platform: Some("ios".into()), ..Default::default() },
+            ..Default::default()
+        };
+        let result = compare_images(request.clone());
+        let passing = CompareRequest { input_image: request.baseline_image.clone(), write_diff: Some(false), ..request.clone() };
+        let ok = ReportEntry { result: compare_images(passing.clone()), request: passing };
+        let failed = ReportEntry { request, result };
+
+        let out = dir.join("report/index.html");
+        write(&out, "Run & 1", &[failed, ok]).unwrap();
+        let html = std::fs::read_to_string(out).unwrap();
+        assert!(html.contains("<title>Run &amp; 1</title>"));
+        assert!(html.contains("2 comparisons: 1 passed, 1 failed"));
+        assert!(html.contains("&lt;login&gt;") && !html.contains("<login>"));
+        assert!(html.contains("data-status=\"Failed\"") && html.contains("data-status=\"Passed\""));
+        assert!(html.contains("<td>Platform</td><td>ios</td>"));
+        assert!(html.contains("<td>Threshold</td><td>99</td>"));
+        // Three images for the failure (with diff), two for the pass.
+        assert_eq!(html.matches("data:image/png;base64,").count(), 5);
+        assert!(html.contains("box excluded\" style=\"left:0.000%;top:0.000%;width:25.000%;height:50.000%"));
+        assert!(html.contains("box changed\" style=\"left:50.000%"));
+        // Diff regions are drawn over the baseline only.
+        let first = &html[..html.find("</section>").unwrap()];
+        let (baseline, input) = first.split_at(first.find("Baseline &mdash;").unwrap());
+        assert!(baseline.contains("box changed") && !input.contains("box changed"));
+    }
+
+    #[test]
+    fn in_memory_images_are_embedded() {
+        let mut png = Vec::new();
+        image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(4, 4, image::Luma([9])))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        let request = CompareRequest {
+            baseline_data: Some(ImageData::Encoded(png)),
+            input_data: Some(ImageData::Rgba { width: 4, height: 4, stride: 16, pixels: vec![9; 64] }),
+            write_diff: Some(false),
+            ..Default::default()
+        };
+        let entry = ReportEntry { result: compare_images(request.clone()), request };
+        let html = render("r", &[entry]);
+        assert_eq!(html.matches("data:image/png;base64,").count(), 2);
+        assert_eq!(html.matches("&mdash; in memory").count(), 2);
+        assert!(!html.contains("not available"));
+    }
+
+    #[test]
+    fn missing_images_are_marked() {
+        let entry = ReportEntry {
+            request: CompareRequest { baseline_image: "/nonexistent/a.png".into(), input_image: "/nonexistent/b.png".into(), ..Default::default() },
+            result: compare_images(CompareRequest { baseline_image: "/nonexistent/a.png".into(), ..Default::default() }),
+        };
+        let html = render("r", &[entry]);
+        assert_eq!(html.matches("not available").count(), 2);
+        assert!(html.contains("data-status=\"Error\""));
+        assert!(html.contains("BaselineMissing"));
+    }
+}
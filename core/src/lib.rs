@@ -1,10 +1,32 @@
 //! Core implementation of the SDK.
 
 pub mod filters;
+pub mod baseline;
+pub mod approval;
+pub mod batch;
+pub mod html_report;
+pub mod test_report;
+pub mod source;
+pub mod cancel;
 pub mod compare;
 pub mod search;
 pub mod locate;
 
-pub use compare::{compare_images, CompareRequest, CompareResult, CompareStatus};
+mod color;
+mod matching;
+mod regions;
+mod render;
+mod ssim;
+
+pub use compare::{
+    compare_images, AlphaDifference, AlphaMode, ColorMode, CompareAlgorithm, CompareError, CompareErrorCode,
+    CompareMetrics, CompareRequest, CompareResolution, CompareResult, CompareStatus, DiffRegion, DiffStyle,
+    DimensionMismatch,
+};
+pub use baseline::BaselineStore;
+pub use cancel::CancelFlag;
+pub use source::{set_fetcher, FetchError, Fetcher, HttpFetcher, ImageData};
+pub use batch::{compare_batch, BatchEntry, BatchRequest, BatchResult};
+pub use approval::{ApprovalError, ApprovalErrorCode, ChangeState, PendingChange};
 pub use search::{flex_search, SearchRequest, SearchResult, MatchRegion};
 pub use locate::{flex_locate, LocateRequest, LocateResult, RelativePosition};
@@ -1,18 +1,31 @@
 use serde::{Deserialize, Serialize};
 
+use crate::cancel::CancelFlag;
+use crate::compare::CompareError;
 use crate::filters::Meta;
+use crate::matching::{self, Hit};
+use crate::source::{self, FetchSettings};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LocateRequest {
     pub container_image: String,
     pub main_image: String,
     pub relative_image: String,
+    /// Timeout for downloading remote images, in milliseconds (default 30000).
+    pub fetch_timeout_ms: Option<u64>,
+    /// Directory caching downloaded images by URL; nothing is cached when unset.
+    pub fetch_cache_dir: Option<String>,
+    /// Seconds a cached download is reused before it is fetched again (default 3600).
+    pub fetch_cache_ttl_secs: Option<u64>,
     #[serde(default)]
     pub meta: Meta,
+    /// Set to stop the search early; the result is then `NotFound`.
+    #[serde(skip)]
+    pub cancel: CancelFlag,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RelativePosition {
     TopLeft,
@@ -26,10 +39,10 @@ pub enum RelativePosition {
     Overlapping,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LocateResult {
-    pub status: String, // "Found" | "NotFound"
+    pub status: String, // "Found" | "NotFound" | "Error"
     #[serde(skip_serializing_if = "Option::is_none")]
     pub main_region: Option<(u32, u32, u32, u32)>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -40,34 +53,198 @@ pub struct LocateResult {
     pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result_image_ref: Option<String>,
+    /// Why the search could not run; set when `status` is "Error".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<CompareError>,
+}
+
+impl RelativePosition {
+    /// Phrase completing "the relative element is ...".
+    fn phrase(self) -> &'static str {
+        match self {
+            RelativePosition::TopLeft => "above and to the left of",
+            RelativePosition::TopRight => "above and to the right of",
+            RelativePosition::BottomLeft => "below and to the left of",
+            RelativePosition::BottomRight => "below and to the right of",
+            RelativePosition::Left => "to the left of",
+            RelativePosition::Right => "to the right of",
+            RelativePosition::Above => "above",
+            RelativePosition::Below => "below",
+            RelativePosition::Overlapping => "overlapping",
+        }
+    }
 }
 
-/// Stub implementation: returns NotFound without regions.
-pub fn flex_locate(_req: LocateRequest) -> LocateResult {
-    LocateResult {
+/// Minimum NCC score (in `[-1, 1]`) for an element to count as located.
+const MIN_MATCH_SCORE: f32 = 0.9;
+
+/// Finds `main_image` and `relative_image` inside `container_image` and
+/// classifies where the relative element sits with respect to the main one.
+/// - Regions are `(top_left_x, top_left_y, bottom_right_x, bottom_right_y)`, inclusive.
+/// - Status is Found only when both elements are located; a single located
+///   element is still reported through its region.
+/// - An image that cannot be opened yields status "Error" with code
+///   `InputMissing`, `DecodeFailed` or `FetchFailed`.
+pub fn flex_locate(req: LocateRequest) -> LocateResult {
+    let mut res = LocateResult {
         status: "NotFound".to_string(),
         main_region: None,
         relative_region: None,
         relative_position_from_main: None,
         description: None,
         result_image_ref: None,
+        error: None,
+    };
+    let failed = |error: CompareError| LocateResult { status: "Error".to_string(), error: Some(error), ..res.clone() };
+
+    let settings = FetchSettings::new(req.fetch_timeout_ms, req.fetch_cache_dir.as_deref(), req.fetch_cache_ttl_secs);
+    let container = match source::open(&req.container_image, &settings) {
+        Ok(img) => img.to_luma8(),
+        Err(e) => return failed(source::open_error(&req.container_image, e)),
+    };
+    let find = |path: &str| -> Result<Option<Hit>, CompareError> {
+        let child = source::open(path, &settings).map_err(|e| source::open_error(path, e))?.to_luma8();
+        Ok(matching::best_match(&container, &child, &req.cancel).filter(|h| h.score >= MIN_MATCH_SCORE))
+    };
+    let (main, relative) = match (find(&req.main_image), find(&req.relative_image)) {
+        (Ok(main), Ok(relative)) => (main, relative),
+        (Err(error), _) | (_, Err(error)) => return failed(error),
+    };
+
+    res.main_region = main.map(region);
+    res.relative_region = relative.map(region);
+    if let (Some(m), Some(r)) = (res.main_region, res.relative_region) {
+        let pos = classify(m, r);
+        res.status = "Found".to_string();
+        res.relative_position_from_main = Some(pos);
+        res.description = Some(match pos {
+            RelativePosition::Overlapping => "The relative element overlaps the main element".to_string(),
+            other => format!("The relative element is {} the main element", other.phrase()),
+        });
+    }
+    res
+}
+
+fn region(hit: Hit) -> (u32, u32, u32, u32) {
+    (hit.x, hit.y, hit.x + hit.width - 1, hit.y + hit.height - 1)
+}
+
+/// Classifies `relative` against `main`. Any shared pixel is Overlapping;
+/// otherwise each axis is Before/After/Aligned depending on whether the
+/// spans are disjoint.
+fn classify(main: (u32, u32, u32, u32), relative: (u32, u32, u32, u32)) -> RelativePosition {
+    let (mx0, my0, mx1, my1) = main;
+    let (rx0, ry0, rx1, ry1) = relative;
+    let left = rx1 < mx0;
+    let right = rx0 > mx1;
+    let above = ry1 < my0;
+    let below = ry0 > my1;
+    match (left, right, above, below) {
+        (true, _, true, _) => RelativePosition::TopLeft,
+        (_, true, true, _) => RelativePosition::TopRight,
+        (true, _, _, true) => RelativePosition::BottomLeft,
+        (_, true, _, true) => RelativePosition::BottomRight,
+        (true, _, _, _) => RelativePosition::Left,
+        (_, true, _, _) => RelativePosition::Right,
+        (_, _, true, _) => RelativePosition::Above,
+        (_, _, _, true) => RelativePosition::Below,
+        _ => RelativePosition::Overlapping,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use image::{GrayImage, Luma};
+
+    fn tmp(file: &str) -> String {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        std::env::temp_dir()
+            .join(format!("vt_locate_{file}_{nanos}.png"))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Container with an "avatar" (dark square with a bright dot) at (20, 20)
+    /// and a "badge" (bright ring on dark) at (100, 30).
+    fn container() -> GrayImage {
+        let mut img = GrayImage::from_pixel(180, 100, Luma([220]));
+        for y in 20..50 {
+            for x in 20..50 {
+                let dot = (30..36).contains(&x) && (30..36).contains(&y);
+                img.put_pixel(x, y, Luma([if dot { 255 } else { 40 }]));
+            }
+        }
+        for y in 30..50 {
+            for x in 100..120 {
+                let ring = x == 100 || x == 119 || y == 30 || y == 49;
+                img.put_pixel(x, y, Luma([if ring { 250 } else { 90 }]));
+            }
+        }
+        img
+    }
+
+    fn save_crop(img: &GrayImage, name: &str, x: u32, y: u32, w: u32, h: u32) -> String {
+        let path = tmp(name);
+        image::imageops::crop_imm(img, x, y, w, h).to_image().save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn badge_is_right_of_avatar() {
+        let img = container();
+        let cp = tmp("container");
+        img.save(&cp).unwrap();
+        let req = LocateRequest {
+            container_image: cp,
+            main_image: save_crop(&img, "avatar", 20, 20, 30, 30),
+            relative_image: save_crop(&img, "badge", 100, 30, 20, 20),
+            ..Default::default()
+        };
+        let res = flex_locate(req.clone());
+        assert_eq!(res.status, "Found");
+        assert_eq!(res.main_region, Some((20, 20, 49, 49)));
+        assert_eq!(res.relative_region, Some((100, 30, 119, 49)));
+        assert_eq!(res.relative_position_from_main, Some(RelativePosition::Right));
+        assert_eq!(
+            res.description.as_deref(),
+            Some("The relative element is to the right of the main element")
+        );
+    }
+
+    #[test]
+    fn classify_covers_all_positions() {
+        let main = (50, 50, 59, 59);
+        let cases = [
+            ((10, 10, 20, 20), RelativePosition::TopLeft),
+            ((70, 10, 80, 20), RelativePosition::TopRight),
+            ((10, 70, 20, 80), RelativePosition::BottomLeft),
+            ((70, 70, 80, 80), RelativePosition::BottomRight),
+            ((10, 52, 20, 58), RelativePosition::Left),
+            ((70, 45, 80, 65), RelativePosition::Right),
+            ((55, 10, 65, 20), RelativePosition::Above),
+            ((40, 70, 70, 80), RelativePosition::Below),
+            ((59, 59, 70, 70), RelativePosition::Overlapping),
+        ];
+        for (rel, expected) in cases {
+            assert_eq!(classify(main, rel), expected, "relative {rel:?}");
+        }
+    }
 
     #[test]
-    fn locate_stub_not_found() {
+    fn locate_missing_files_are_errors() {
         let req = LocateRequest {
             container_image: "container.png".into(),
             main_image: "main.png".into(),
             relative_image: "relative.png".into(),
-            meta: Meta::default(),
+            ..Default::default()
         };
         let res = flex_locate(req);
-        assert_eq!(res.status, "NotFound");
+        assert_eq!(res.status, "Error");
+        assert_eq!(res.error.map(|e| e.code), Some(crate::compare::CompareErrorCode::InputMissing));
         assert!(res.main_region.is_none());
         assert!(res.relative_region.is_none());
         assert!(res.relative_position_from_main.is_none());
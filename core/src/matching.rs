@@ -0,0 +1,347 @@
+//! Normalized cross-correlation (NCC) template matching shared by search and locate.
+//!
+//! Scores are zero-mean NCC in `[-1, 1]`. Large inputs are matched on a coarse
+//! pyramid level first and the best candidates are refined at full resolution.
+
+use image::{imageops::FilterType, GrayImage};
+
+use crate::cancel::CancelFlag;
+
+/// A template hit in parent-image coordinates.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Hit {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub score: f32,
+}
+
+impl Hit {
+    pub fn center(&self) -> (f32, f32) {
+        (
+            self.x as f32 + self.width as f32 / 2.0,
+            self.y as f32 + self.height as f32 / 2.0,
+        )
+    }
+}
+
+/// Multiply-adds allowed for a brute-force pass before switching to a pyramid.
+const BRUTE_FORCE_BUDGET: u64 = 40_000_000;
+/// Smallest template side we are willing to downscale to.
+const MIN_PYRAMID_SIDE: u32 = 8;
+/// Number of coarse peaks refined at full resolution.
+const MAX_CANDIDATES: usize = 8;
+/// Number of peaks kept when looking for every occurrence.
+const MAX_OCCURRENCE_CANDIDATES: usize = 256;
+/// How far below the threshold a coarse peak may score and still be refined;
+/// downscaling blurs fine texture and lowers the score.
+const COARSE_SLACK: f32 = 0.25;
+
+/// Returns the best-scoring placement of `child` inside `parent`, if it fits.
+/// Cancellation yields `None`.
+pub(crate) fn best_match(parent: &GrayImage, child: &GrayImage, cancel: &CancelFlag) -> Option<Hit> {
+    first_best(scan(parent, child, None, cancel))
+}
+
+/// Highest-scoring hit; ties go to the first, i.e. the top-left-most placement.
+fn first_best(hits: impl IntoIterator<Item = Hit>) -> Option<Hit> {
+    hits.into_iter().min_by(|a, b| b.score.total_cmp(&a.score))
+}
+
+/// Returns every locally best placement scoring at least `min_score`. Nearby
+/// placements of one occurrence can all be returned; callers suppress overlaps.
+pub(crate) fn all_matches(parent: &GrayImage, child: &GrayImage, min_score: f32, cancel: &CancelFlag) -> Vec<Hit> {
+    scan(parent, child, Some(min_score), cancel)
+}
+
+/// Scores placements of `child`. Without `threshold` only the best is kept;
+/// with one, each local maximum at or above it. Returns nothing once `cancel` is set.
+fn scan(parent: &GrayImage, child: &GrayImage, threshold: Option<f32>, cancel: &CancelFlag) -> Vec<Hit> {
+    let (pw, ph) = parent.dimensions();
+    let (cw, ch) = child.dimensions();
+    if cw == 0 || ch == 0 || cw > pw || ch > ph {
+        return vec![];
+    }
+
+    let positions = (pw - cw + 1) as u64 * (ph - ch + 1) as u64;
+    let cost = positions * cw as u64 * ch as u64;
+    let mut factor = 1u32;
+    while cost / (factor as u64).pow(4) > BRUTE_FORCE_BUDGET
+        && cw / (factor * 2) >= MIN_PYRAMID_SIDE
+        && ch / (factor * 2) >= MIN_PYRAMID_SIDE
+    {
+        factor *= 2;
+    }
+
+    let full = Plane::new(parent);
+    let tpl = Template::new(child);
+
+    if factor == 1 {
+        let (sw, sh) = (pw - cw + 1, ph - ch + 1);
+        let Some(t) = threshold else {
+            let mut best: Option<Hit> = None;
+            for y in 0..sh {
+                if cancel.is_cancelled() {
+                    return vec![];
+                }
+                for x in 0..sw {
+                    let score = full.score_at(&tpl, x, y);
+                    if best.is_none_or(|b| score > b.score) {
+                        best = Some(Hit { x, y, width: cw, height: ch, score });
+                    }
+                }
+            }
+            return best.into_iter().collect();
+        };
+        // Local maxima need their neighbours' scores, so keep the whole map.
+        let mut scores: Vec<f32> = Vec::with_capacity((sw * sh) as usize);
+        for y in 0..sh {
+            if cancel.is_cancelled() {
+                return vec![];
+            }
+            scores.extend((0..sw).map(|x| full.score_at(&tpl, x, y)));
+        }
+        let hit = |i: usize| Hit { x: i as u32 % sw, y: i as u32 / sw, width: cw, height: ch, score: scores[i] };
+        let mut hits: Vec<Hit> = (0..scores.len()).filter(|&i| scores[i] >= t && is_local_max(&scores, sw, sh, i)).map(hit).collect();
+        // Same cap as the pyramid path, keeping the strongest.
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(MAX_OCCURRENCE_CANDIDATES);
+        return hits;
+    }
+
+    // Coarse pass on a downscaled pyramid level.
+    let small_parent = image::imageops::resize(parent, (pw / factor).max(1), (ph / factor).max(1), FilterType::Triangle);
+    let small_child = image::imageops::resize(child, (cw / factor).max(1), (ch / factor).max(1), FilterType::Triangle);
+    let coarse = Plane::new(&small_parent);
+    let coarse_tpl = Template::new(&small_child);
+    let (sw, sh) = small_parent.dimensions();
+    let (tw, th) = small_child.dimensions();
+    if tw > sw || th > sh {
+        return vec![];
+    }
+    let mut scored = Vec::with_capacity(((sw - tw + 1) * (sh - th + 1)) as usize);
+    for y in 0..=(sh - th) {
+        if cancel.is_cancelled() {
+            return vec![];
+        }
+        for x in 0..=(sw - tw) {
+            scored.push((x, y, coarse.score_at(&coarse_tpl, x, y)));
+        }
+    }
+    scored.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    // Greedy peak picking: skip positions too close to an accepted peak.
+    let (limit, floor) = match threshold {
+        None => (MAX_CANDIDATES, f32::NEG_INFINITY),
+        Some(t) => (MAX_OCCURRENCE_CANDIDATES, t - COARSE_SLACK),
+    };
+    let (rx, ry) = ((tw / 2).max(1), (th / 2).max(1));
+    let mut peaks: Vec<(u32, u32)> = Vec::new();
+    for (x, y, _) in scored.into_iter().take_while(|p| p.2 >= floor) {
+        if peaks.len() >= limit {
+            break;
+        }
+        if peaks.iter().all(|&(px, py)| px.abs_diff(x) > rx || py.abs_diff(y) > ry) {
+            peaks.push((x, y));
+        }
+    }
+
+    // Refine each peak at full resolution within one coarse cell.
+    let mut hits: Vec<Hit> = Vec::new();
+    for (x, y) in peaks {
+        if cancel.is_cancelled() {
+            return vec![];
+        }
+        let cx = x * factor;
+        let cy = y * factor;
+        let x0 = cx.saturating_sub(factor);
+        let y0 = cy.saturating_sub(factor);
+        let x1 = (cx + factor).min(pw - cw);
+        let y1 = (cy + factor).min(ph - ch);
+        let mut best: Option<Hit> = None;
+        for yy in y0..=y1 {
+            for xx in x0..=x1 {
+                let score = full.score_at(&tpl, xx, yy);
+                if best.is_none_or(|b| score > b.score) {
+                    best = Some(Hit { x: xx, y: yy, width: cw, height: ch, score });
+                }
+            }
+        }
+        hits.extend(best.filter(|b| threshold.is_none_or(|t| b.score >= t)));
+    }
+    hits
+}
+
+/// Whether no 8-neighbour of cell `i` in a `w`x`h` grid scores higher.
+fn is_local_max(scores: &[f32], w: u32, h: u32, i: usize) -> bool {
+    let (x, y) = ((i as u32 % w) as i64, (i as u32 / w) as i64);
+    (-1..=1i64).flat_map(|dy| (-1..=1i64).map(move |dx| (x + dx, y + dy))).all(|(nx, ny)| {
+        nx < 0 || ny < 0 || nx >= w as i64 || ny >= h as i64 || scores[(ny * w as i64 + nx) as usize] <= scores[i]
+    })
+}
+
+/// Parent image as floats plus integral images of values and squared values.
+struct Plane {
+    width: usize,
+    pixels: Vec<f32>,
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl Plane {
+    fn new(img: &GrayImage) -> Self {
+        let (w, h) = (img.width() as usize, img.height() as usize);
+        let pixels: Vec<f32> = img.as_raw().iter().map(|&v| v as f32).collect();
+        let stride = w + 1;
+        let mut sum = vec![0.0f64; stride * (h + 1)];
+        let mut sum_sq = vec![0.0f64; stride * (h + 1)];
+        for y in 0..h {
+            let mut row = 0.0f64;
+            let mut row_sq = 0.0f64;
+            for x in 0..w {
+                let v = pixels[y * w + x] as f64;
+                row += v;
+                row_sq += v * v;
+                sum[(y + 1) * stride + x + 1] = sum[y * stride + x + 1] + row;
+                sum_sq[(y + 1) * stride + x + 1] = sum_sq[y * stride + x + 1] + row_sq;
+            }
+        }
+        Plane { width: w, pixels, sum, sum_sq }
+    }
+
+    fn rect_sums(&self, x: usize, y: usize, w: usize, h: usize) -> (f64, f64) {
+        let s = self.width + 1;
+        let at = |t: &Vec<f64>, xx: usize, yy: usize| t[yy * s + xx];
+        let sum = at(&self.sum, x + w, y + h) - at(&self.sum, x, y + h) - at(&self.sum, x + w, y) + at(&self.sum, x, y);
+        let sq = at(&self.sum_sq, x + w, y + h) - at(&self.sum_sq, x, y + h) - at(&self.sum_sq, x + w, y)
+            + at(&self.sum_sq, x, y);
+        (sum, sq)
+    }
+
+    /// Zero-mean NCC of the template placed with its top-left corner at (x, y).
+    fn score_at(&self, tpl: &Template, x: u32, y: u32) -> f32 {
+        let (x, y) = (x as usize, y as usize);
+        let n = (tpl.width * tpl.height) as f64;
+        let (sum, sq) = self.rect_sums(x, y, tpl.width, tpl.height);
+        let var = (sq - sum * sum / n).max(0.0);
+
+        if tpl.norm < 1e-6 {
+            // Flat template: only a flat patch of similar brightness is a match.
+            if var / n > 1.0 {
+                return 0.0;
+            }
+            return (1.0 - ((sum / n) as f32 - tpl.mean).abs() / 255.0).clamp(0.0, 1.0);
+        }
+        if var < 1e-6 {
+            return 0.0;
+        }
+
+        let mut num = 0.0f64;
+        for j in 0..tpl.height {
+            let row = &self.pixels[(y + j) * self.width + x..][..tpl.width];
+            let trow = &tpl.values[j * tpl.width..][..tpl.width];
+            let mut acc = 0.0f32;
+            for (p, t) in row.iter().zip(trow) {
+                acc += p * t;
+            }
+            num += acc as f64;
+        }
+        (num / (tpl.norm as f64 * var.sqrt())).clamp(-1.0, 1.0) as f32
+    }
+}
+
+/// Template with its mean removed, so NCC needs only one dot product per placement.
+struct Template {
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+    mean: f32,
+    norm: f32,
+}
+
+impl Template {
+    fn new(img: &GrayImage) -> Self {
+        let raw = img.as_raw();
+        let mean = raw.iter().map(|&v| v as f32).sum::<f32>() / raw.len().max(1) as f32;
+        let values: Vec<f32> = raw.iter().map(|&v| v as f32 - mean).collect();
+        let norm = values.iter().map(|v| v * v).sum::<f32>().sqrt();
+        Template { width: img.width() as usize, height: img.height() as usize, values, mean, norm }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    // Pseudo-random 8x8 blocks: unique everywhere and still textured after downscaling.
+    fn pattern(w: u32, h: u32) -> GrayImage {
+        GrayImage::from_fn(w, h, |x, y| {
+            let h = (x / 8).wrapping_mul(73_856_093) ^ (y / 8).wrapping_mul(19_349_663);
+            Luma([(h.wrapping_mul(2_654_435_761) >> 24) as u8])
+        })
+    }
+
+    #[test]
+    fn finds_exact_crop() {
+        let parent = pattern(120, 90);
+        let child = image::imageops::crop_imm(&parent, 41, 27, 16, 12).to_image();
+        let hit = best_match(&parent, &child, &CancelFlag::new()).expect("match");
+        assert_eq!((hit.x, hit.y), (41, 27));
+        assert!(hit.score > 0.999, "score {}", hit.score);
+    }
+
+    #[test]
+    fn pyramid_refines_to_exact_position() {
+        let parent = pattern(900, 700);
+        let child = image::imageops::crop_imm(&parent, 503, 311, 64, 48).to_image();
+        let hit = best_match(&parent, &child, &CancelFlag::new()).expect("match");
+        assert_eq!((hit.x, hit.y), (503, 311));
+    }
+
+    #[test]
+    fn child_larger_than_parent_has_no_match() {
+        assert!(best_match(&pattern(10, 10), &pattern(11, 5), &CancelFlag::new()).is_none());
+    }
+
+    #[test]
+    fn finds_every_occurrence() {
+        let tile = pattern(16, 16);
+        let mut parent = GrayImage::from_pixel(120, 60, Luma([200]));
+        for x in [5, 50, 95] {
+            image::imageops::replace(&mut parent, &tile, x, 20);
+        }
+        let mut hits = all_matches(&parent, &tile, 0.95, &CancelFlag::new());
+        hits.sort_by_key(|h| h.x);
+        let positions: Vec<_> = hits.iter().map(|h| (h.x, h.y)).collect();
+        assert_eq!(positions, vec![(5, 20), (50, 20), (95, 20)]);
+
+        // A low threshold on per-pixel noise keeps only the strongest peaks.
+        let busy = GrayImage::from_fn(120, 90, |x, y| Luma([((x * 7919 + y * 104_729) % 251) as u8]));
+        let tile = image::imageops::crop_imm(&busy, 10, 10, 6, 6).to_image();
+        let hits = all_matches(&busy, &tile, -1.0, &CancelFlag::new());
+        assert_eq!(hits.len(), MAX_OCCURRENCE_CANDIDATES);
+        assert!(hits.windows(2).all(|w| w[0].score >= w[1].score));
+    }
+
+    #[test]
+    fn pyramid_finds_every_occurrence() {
+        let tile = pattern(64, 48);
+        let mut parent = GrayImage::from_fn(900, 400, |x, y| Luma([((x * 7 + y * 13) % 50) as u8]));
+        for (x, y) in [(40, 30), (500, 300), (700, 40)] {
+            image::imageops::replace(&mut parent, &tile, x, y);
+        }
+        let mut hits = all_matches(&parent, &tile, 0.95, &CancelFlag::new());
+        hits.sort_by_key(|h| h.x);
+        let positions: Vec<_> = hits.iter().map(|h| (h.x, h.y)).collect();
+        assert_eq!(positions, vec![(40, 30), (500, 300), (700, 40)]);
+        assert!(all_matches(&parent, &tile, 1.1, &CancelFlag::new()).is_empty());
+
+        let cancelled = CancelFlag::new();
+        cancelled.cancel();
+        assert!(all_matches(&parent, &tile, 0.95, &cancelled).is_empty());
+        assert!(best_match(&parent, &tile, &cancelled).is_none());
+        assert!(best_match(&pattern(40, 30), &pattern(8, 8), &cancelled).is_none());
+    }
+}
@@ -0,0 +1,90 @@
+//! Connected-component labelling of a per-pixel difference map.
+
+/// A group of 8-connected changed pixels in working-resolution coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Component {
+    pub min_x: u32,
+    pub min_y: u32,
+    pub max_x: u32,
+    pub max_y: u32,
+    pub pixels: u32,
+    /// Sum of the pixel differences (0..255 each).
+    pub total: f64,
+}
+
+/// Labels every included pixel with a non-zero difference. Components are
+/// returned largest first.
+pub(crate) fn components(diffs: &[f32], mask: &[bool], width: u32, height: u32) -> Vec<Component> {
+    let (w, h) = (width as usize, height as usize);
+    let changed = |i: usize| diffs[i] > 0.0 && mask[i];
+    let mut seen = vec![false; w * h];
+    let mut out = Vec::new();
+    let mut stack = Vec::new();
+
+    for start in 0..w * h {
+        if seen[start] || !changed(start) {
+            continue;
+        }
+        seen[start] = true;
+        stack.push(start);
+        let mut c = Component {
+            min_x: u32::MAX,
+            min_y: u32::MAX,
+            max_x: 0,
+            max_y: 0,
+            pixels: 0,
+            total: 0.0,
+        };
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % w, i / w);
+            c.min_x = c.min_x.min(x as u32);
+            c.min_y = c.min_y.min(y as u32);
+            c.max_x = c.max_x.max(x as u32);
+            c.max_y = c.max_y.max(y as u32);
+            c.pixels += 1;
+            c.total += diffs[i] as f64;
+            for ny in y.saturating_sub(1)..=(y + 1).min(h - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(w - 1) {
+                    let j = ny * w + nx;
+                    if !seen[j] && changed(j) {
+                        seen[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+        }
+        out.push(c);
+    }
+    out.sort_by_key(|c| std::cmp::Reverse(c.pixels));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_blobs_and_joins_diagonals() {
+        // 6x4 map: a diagonal pair at top-left and a 2x2 block at bottom-right.
+        let mut diffs = vec![0.0f32; 24];
+        diffs[0] = 10.0;
+        diffs[7] = 30.0;
+        for i in [16, 17, 22, 23] {
+            diffs[i] = 255.0;
+        }
+        let mask = vec![true; 24];
+        let comps = components(&diffs, &mask, 6, 4);
+        assert_eq!(comps.len(), 2);
+        assert_eq!((comps[0].min_x, comps[0].min_y, comps[0].max_x, comps[0].max_y), (4, 2, 5, 3));
+        assert_eq!(comps[0].pixels, 4);
+        assert_eq!((comps[1].min_x, comps[1].min_y, comps[1].max_x, comps[1].max_y), (0, 0, 1, 1));
+        assert_eq!(comps[1].total, 40.0);
+    }
+
+    #[test]
+    fn masked_pixels_are_not_labelled() {
+        let diffs = vec![5.0f32; 9];
+        let mask = vec![false; 9];
+        assert!(components(&diffs, &mask, 3, 3).is_empty());
+    }
+}
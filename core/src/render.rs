@@ -0,0 +1,136 @@
+//! Diff image rendering for `CompareResult.result_image_ref`.
+
+use std::path::Path;
+
+use image::{imageops::FilterType, GrayImage, ImageResult, Luma, Rgb, RgbImage, RgbaImage};
+
+use crate::compare::DiffStyle;
+
+const HIGHLIGHT: [u8; 3] = [255, 0, 0];
+/// Gap between panels of a side-by-side composite.
+const PANEL_GAP: u32 = 8;
+const PANEL_GAP_COLOR: [u8; 3] = [40, 40, 40];
+/// How long each blink frame is shown.
+const BLINK_FRAME_MS: u32 = 500;
+/// Longest side of a blink frame. GIF encoding quantizes every frame to 256
+/// colours, which takes seconds for a full-resolution phone screenshot.
+const BLINK_MAX_SIDE: u32 = 800;
+/// Quantizer speed for blink frames (1..=30): samples fewer pixels than the
+/// encoder's highest-quality default, with little visible loss on screenshots.
+const BLINK_GIF_SPEED: i32 = 10;
+
+/// Per-pixel differences (0..255) at the comparison's working resolution.
+pub(crate) struct DiffMap<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub diffs: &'a [f32],
+    pub mask: &'a [bool],
+}
+
+impl DiffMap<'_> {
+    /// Whether pixel (x, y) of an image of size (w, h) falls on a changed, included pixel.
+    fn changed_at(&self, x: u32, y: u32, w: u32, h: u32) -> bool {
+        let mx = (x as u64 * self.width as u64 / w.max(1) as u64) as usize;
+        let my = (y as u64 * self.height as u64 / h.max(1) as u64) as usize;
+        let i = my * self.width as usize + mx;
+        self.mask[i] && self.diffs[i] > 0.0
+    }
+}
+
+/// File extension for images written in `style`.
+pub(crate) fn extension(style: DiffStyle) -> &'static str {
+    match style {
+        DiffStyle::Blink => "gif",
+        _ => "png",
+    }
+}
+
+/// Renders and saves the diff. `baseline` and `input` are the flattened
+/// images at their native size.
+pub(crate) fn write(
+    style: DiffStyle,
+    baseline: &RgbImage,
+    input: &RgbImage,
+    map: &DiffMap,
+    path: &Path,
+) -> ImageResult<()> {
+    match style {
+        DiffStyle::Grayscale => grayscale(map).save(path),
+        DiffStyle::Overlay => overlay(input, map).save(path),
+        DiffStyle::SideBySide => side_by_side(&[baseline, input, &overlay(input, map)]).save(path),
+        DiffStyle::Blink => blink(baseline, input, path),
+    }
+}
+
+/// Absolute difference at working resolution.
+fn grayscale(map: &DiffMap) -> GrayImage {
+    GrayImage::from_fn(map.width, map.height, |x, y| {
+        let d = map.diffs[(y * map.width + x) as usize];
+        Luma([d.round().min(255.0) as u8])
+    })
+}
+
+/// Changed pixels tinted red; unchanged pixels faded towards white so the
+/// highlights stand out.
+fn overlay(input: &RgbImage, map: &DiffMap) -> RgbImage {
+    let (w, h) = input.dimensions();
+    RgbImage::from_fn(w, h, |x, y| {
+        let p = input.get_pixel(x, y).0;
+        if map.changed_at(x, y, w, h) {
+            Rgb(mix(p, HIGHLIGHT, 0.7))
+        } else {
+            Rgb(mix(p, [255, 255, 255], 0.6))
+        }
+    })
+}
+
+fn side_by_side(panels: &[&RgbImage]) -> RgbImage {
+    let width = panels.iter().map(|p| p.width()).sum::<u32>() + PANEL_GAP * (panels.len() as u32 - 1);
+    let height = panels.iter().map(|p| p.height()).max().unwrap_or(0);
+    let mut out = RgbImage::from_pixel(width, height, Rgb(PANEL_GAP_COLOR));
+    let mut x = 0;
+    for panel in panels {
+        image::imageops::replace(&mut out, *panel, x as i64, 0);
+        x += panel.width() + PANEL_GAP;
+    }
+    out
+}
+
+/// Looping GIF alternating baseline and input at the baseline's size, scaled
+/// down to at most `BLINK_MAX_SIDE` pixels per side to bound encoding time.
+/// The input is stretched to that size if it differs.
+fn blink(baseline: &RgbImage, input: &RgbImage, path: &Path) -> ImageResult<()> {
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, Frame};
+
+    let (bw, bh) = baseline.dimensions();
+    let scale = (BLINK_MAX_SIDE as f32 / bw.max(bh) as f32).min(1.0);
+    let (w, h) = (((bw as f32 * scale).round() as u32).max(1), ((bh as f32 * scale).round() as u32).max(1));
+    let frame = |img: &RgbImage| {
+        let img = if img.dimensions() == (w, h) {
+            img.clone()
+        } else {
+            // Nearest keeps a stretched input crisp; Triangle avoids aliasing when shrinking.
+            let filter = if scale < 1.0 { FilterType::Triangle } else { FilterType::Nearest };
+            image::imageops::resize(img, w, h, filter)
+        };
+        RgbaImage::from_fn(w, h, |x, y| {
+            let [r, g, b] = img.get_pixel(x, y).0;
+            image::Rgba([r, g, b, 255])
+        })
+    };
+
+    let file = std::fs::File::create(path)?;
+    let mut encoder = GifEncoder::new_with_speed(std::io::BufWriter::new(file), BLINK_GIF_SPEED);
+    encoder.set_repeat(Repeat::Infinite)?;
+    let delay = Delay::from_numer_denom_ms(BLINK_FRAME_MS, 1);
+    encoder.encode_frames([
+        Frame::from_parts(frame(baseline), 0, 0, delay),
+        Frame::from_parts(frame(input), 0, 0, delay),
+    ])
+}
+
+fn mix(a: [u8; 3], b: [u8; 3], t: f32) -> [u8; 3] {
+    let m = |x: u8, y: u8| (x as f32 * (1.0 - t) + y as f32 * t).round() as u8;
+    [m(a[0], b[0]), m(a[1], b[1]), m(a[2], b[2])]
+}
@@ -1,29 +1,65 @@
+use image::imageops::FilterType;
+use image::GrayImage;
 use serde::{Deserialize, Serialize};
 
+use crate::cancel::CancelFlag;
 use crate::filters::Meta;
+use crate::matching::{self, Hit};
+use crate::compare::{CompareError, CompareErrorCode};
+use crate::source::{self, FetchSettings};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchRequest {
     pub parent_image: String,
     pub child_image: String,
+    /// Smallest child scale tried, e.g. 0.5 for a @2x asset on a @1x screen (default 1).
+    pub min_scale: Option<f32>,
+    /// Largest child scale tried, e.g. 3 for a @1x asset on a @3x screen (default 1).
+    pub max_scale: Option<f32>,
+    /// Ratio between consecutive scales tried, greater than 1 (default 1.1, raised to at least 1.01).
+    pub scale_step: Option<f32>,
+    /// Minimum score (0..100) for a placement to count as a match (default 90).
+    pub min_confidence: Option<f32>,
+    /// Largest overlap (intersection over union, 0..1) allowed between two
+    /// matches; the weaker of two more-overlapping matches is dropped (default 0.3).
+    pub max_overlap: Option<f32>,
+    /// Timeout for downloading remote images, in milliseconds (default 30000).
+    pub fetch_timeout_ms: Option<u64>,
+    /// Directory caching downloaded images by URL; nothing is cached when unset.
+    pub fetch_cache_dir: Option<String>,
+    /// Seconds a cached download is reused before it is fetched again (default 3600).
+    pub fetch_cache_ttl_secs: Option<u64>,
     #[serde(default)]
     pub meta: Meta,
+    /// Set to stop the search early; the result is then `NotFound`.
+    #[serde(skip)]
+    pub cancel: CancelFlag,
 }
 
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MatchRegion {
     pub top_left_x: u32,
     pub top_left_y: u32,
     pub bottom_right_x: u32,
     pub bottom_right_y: u32,
+    /// Scale of the child image at this match (1 = as captured).
+    #[serde(default = "unit_scale")]
+    pub scale: f32,
+    /// Match score on the `precision` scale, 0..100.
+    #[serde(default)]
+    pub score: f32,
+}
+
+fn unit_scale() -> f32 {
+    1.0
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchResult {
-    pub status: String, // "Found" | "NotFound"
+    pub status: String, // "Found" | "NotFound" | "Error"
     pub total_matches: u32,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub matches: Vec<MatchRegion>,
@@ -33,35 +69,432 @@ pub struct SearchResult {
     pub precision: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub center: Option<(f32, f32)>,
+    /// Why the search could not run; set when `status` is "Error".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<CompareError>,
 }
 
-/// Stub implementation: returns NotFound with no matches.
-pub fn flex_search(_req: SearchRequest) -> SearchResult {
-    SearchResult {
+/// Minimum NCC score (in `[-1, 1]`) for a placement to count as a match.
+const MIN_MATCH_SCORE: f32 = 0.9;
+/// Overlap (IoU) above which the weaker of two matches is suppressed.
+const DEFAULT_MAX_OVERLAP: f32 = 0.3;
+/// Scale ratio used when `scale_step` is unset.
+const DEFAULT_SCALE_STEP: f32 = 1.1;
+/// Finest scale ratio honoured, which bounds the number of scales tried.
+const MIN_SCALE_STEP: f32 = 1.01;
+/// Scaled children smaller than this (in pixels per side) are skipped.
+const MIN_SCALED_SIDE: u32 = 4;
+/// Finer scales tried on each side of the best grid scale.
+const SCALE_REFINE_STEPS: i32 = 3;
+
+/// Finds every occurrence of `child_image` inside `parent_image` using
+/// normalized cross-correlation on luminance.
+/// - `matches` lists each placement scoring at least `min_confidence`, best
+///   first, after non-maximum suppression of overlapping duplicates.
+/// - `precision` is the NCC score of the best match, scaled to 0..100.
+/// - `center` is the center of the best match in parent coordinates.
+/// - With `min_scale`/`max_scale` the child is also resized across that range
+///   (a geometric grid of `scale_step`, refined around the best scale), and the
+///   matched scale is reported per `MatchRegion`. Further occurrences are
+///   searched at the scale of the best match. Scales are capped where the
+///   child still fits in the parent.
+/// - A child larger than the parent or a set `cancel` flag yields NotFound.
+/// - If the search cannot run, `status` is "Error" and `error` carries the
+///   code: `InputMissing`, `DecodeFailed` or `FetchFailed` for an image that
+///   cannot be opened, `InvalidArgument` for a non-finite or non-positive
+///   scale, a `scale_step` of at most 1, or a NaN `min_confidence` or
+///   `max_overlap`.
+pub fn flex_search(req: SearchRequest) -> SearchResult {
+    let not_found = SearchResult {
         status: "NotFound".to_string(),
         total_matches: 0,
         matches: vec![],
         result_image_ref: None,
         precision: None,
         center: None,
+        error: None,
+    };
+
+    let failed = |error: CompareError| SearchResult { status: "Error".to_string(), error: Some(error), ..not_found.clone() };
+    if let Some(message) = invalid_option(&req) {
+        return failed(CompareError { code: CompareErrorCode::InvalidArgument, message });
+    }
+    let settings = FetchSettings::new(req.fetch_timeout_ms, req.fetch_cache_dir.as_deref(), req.fetch_cache_ttl_secs);
+    let open = |location: &str| source::open(location, &settings).map_err(|e| source::open_error(location, e));
+    let (parent, child) = match (open(&req.parent_image), open(&req.child_image)) {
+        (Ok(p), Ok(c)) => (p.to_luma8(), c.to_luma8()),
+        (Err(error), _) | (_, Err(error)) => return failed(error),
+    };
+
+    let Some((best, scale)) = best_scaled_match(&parent, &child, &req) else {
+        return not_found;
+    };
+    let min_score = req.min_confidence.map_or(MIN_MATCH_SCORE, |c| c.clamp(0.0, 100.0) / 100.0);
+    if best.score < min_score {
+        return SearchResult { precision: Some(best.score.max(0.0) * 100.0), ..not_found };
+    }
+
+    let scaled = if scale == 1.0 { None } else { resize(&child, scale, parent.dimensions()) };
+    let mut hits = vec![best];
+    hits.extend(matching::all_matches(&parent, scaled.as_ref().unwrap_or(&child), min_score, &req.cancel));
+    if req.cancel.is_cancelled() {
+        return not_found;
+    }
+    let max_overlap = req.max_overlap.map_or(DEFAULT_MAX_OVERLAP, |o| o.clamp(0.0, 1.0));
+    let matches: Vec<MatchRegion> = suppress_overlaps(hits, max_overlap)
+        .into_iter()
+        .map(|hit| MatchRegion {
+            top_left_x: hit.x,
+            top_left_y: hit.y,
+            bottom_right_x: hit.x + hit.width - 1,
+            bottom_right_y: hit.y + hit.height - 1,
+            scale,
+            score: hit.score * 100.0,
+        })
+        .collect();
+    SearchResult {
+        status: "Found".to_string(),
+        total_matches: matches.len() as u32,
+        matches,
+        result_image_ref: None,
+        precision: Some(best.score * 100.0),
+        center: Some(best.center()),
+        error: None,
+    }
+}
+
+/// Why the numeric options of `req` cannot be used, if they cannot.
+fn invalid_option(req: &SearchRequest) -> Option<String> {
+    let positive = |name: &str, value: Option<f32>| {
+        value.filter(|v| !v.is_finite() || *v <= 0.0).map(|v| format!("{name} must be a positive number, got {v}"))
+    };
+    let number = |name: &str, value: Option<f32>| value.filter(|v| v.is_nan()).map(|_| format!("{name} must be a number"));
+    positive("minScale", req.min_scale)
+        .or_else(|| positive("maxScale", req.max_scale))
+        .or_else(|| positive("scaleStep", req.scale_step))
+        .or_else(|| req.scale_step.filter(|s| *s <= 1.0).map(|s| format!("scaleStep must be greater than 1, got {s}")))
+        .or_else(|| number("minConfidence", req.min_confidence))
+        .or_else(|| number("maxOverlap", req.max_overlap))
+}
+
+/// Non-maximum suppression: keeps hits from best to worst, dropping any whose
+/// overlap with an already kept hit exceeds `max_overlap`. A placement already
+/// kept is always dropped, even when any overlap is allowed.
+fn suppress_overlaps(mut hits: Vec<Hit>, max_overlap: f32) -> Vec<Hit> {
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut kept: Vec<Hit> = Vec::new();
+    for hit in hits {
+        let same = |k: &Hit| (k.x, k.y, k.width, k.height) == (hit.x, hit.y, hit.width, hit.height);
+        if kept.iter().all(|k| !same(k) && overlap(k, &hit) <= max_overlap) {
+            kept.push(hit);
+        }
+    }
+    kept
+}
+
+/// Intersection over union of two hits.
+fn overlap(a: &Hit, b: &Hit) -> f32 {
+    let w = (a.x + a.width).min(b.x + b.width).saturating_sub(a.x.max(b.x)) as f32;
+    let h = (a.y + a.height).min(b.y + b.height).saturating_sub(a.y.max(b.y)) as f32;
+    let union = (a.width * a.height + b.width * b.height) as f32 - w * h;
+    if union > 0.0 { w * h / union } else { 0.0 }
+}
+
+/// Best placement of `child` over the requested scale range, with its scale.
+/// Stops trying scales once `req.cancel` is set and then returns `None`.
+fn best_scaled_match(parent: &GrayImage, child: &GrayImage, req: &SearchRequest) -> Option<(Hit, f32)> {
+    let (pw, ph) = parent.dimensions();
+    let (cw, ch) = child.dimensions();
+    if cw == 0 || ch == 0 {
+        return None;
+    }
+    // Only scales at which the child keeps a few pixels and still fits are worth trying.
+    let smallest = (MIN_SCALED_SIDE as f32 / cw.min(ch) as f32).min(1.0);
+    let largest = (pw as f32 / cw as f32).min(ph as f32 / ch as f32);
+    let min = req.min_scale.unwrap_or(1.0).max(smallest);
+    let max = req.max_scale.unwrap_or(1.0).max(min).min(largest);
+    if max < min {
+        return None;
+    }
+    let step = req.scale_step.map_or(DEFAULT_SCALE_STEP, |s| s.max(MIN_SCALE_STEP));
+    let cancel = &req.cancel;
+    let at = |scale: f32| {
+        if cancel.is_cancelled() {
+            return None;
+        }
+        let hit = if scale == 1.0 {
+            matching::best_match(parent, child, cancel)
+        } else {
+            matching::best_match(parent, &resize(child, scale, (pw, ph))?, cancel)
+        };
+        hit.map(|h| (h, scale))
+    };
+    let better = |a: Option<(Hit, f32)>, b: Option<(Hit, f32)>| match (a, b) {
+        (Some(a), Some(b)) => Some(if b.0.score > a.0.score { b } else { a }),
+        (a, b) => a.or(b),
+    };
+
+    let best = scale_grid(min, max, step).into_iter().fold(None, |best, s| better(best, at(s)));
+    if min == max || cancel.is_cancelled() {
+        return best.filter(|_| !cancel.is_cancelled());
+    }
+    // The grid is coarse; try finer scales between the best one and its neighbours.
+    let (_, coarse) = best?;
+    (-SCALE_REFINE_STEPS..=SCALE_REFINE_STEPS)
+        .filter(|k| *k != 0)
+        .map(|k| coarse * step.powf(k as f32 / (SCALE_REFINE_STEPS + 1) as f32))
+        .filter(|s| (min..=max).contains(s))
+        .fold(best, |best, s| better(best, at(s)))
+        .filter(|_| !cancel.is_cancelled())
+}
+
+/// Powers of `step` within `[min, max]` (so 1 is included when in range), plus both ends.
+fn scale_grid(min: f32, max: f32, step: f32) -> Vec<f32> {
+    let (lo, hi) = ((min.ln() / step.ln()).ceil() as i32, (max.ln() / step.ln()).floor() as i32);
+    let mut scales = vec![min];
+    scales.extend((lo..=hi).map(|k| step.powi(k)).filter(|s| *s > min && *s < max));
+    if max > min {
+        scales.push(max);
     }
+    scales
+}
+
+/// `child` resized by `scale`, unless it would be tiny or not fit in `bounds`.
+fn resize(child: &GrayImage, scale: f32, bounds: (u32, u32)) -> Option<GrayImage> {
+    let w = (child.width() as f32 * scale).round() as u32;
+    let h = (child.height() as f32 * scale).round() as u32;
+    if w < MIN_SCALED_SIDE || h < MIN_SCALED_SIDE || w > bounds.0 || h > bounds.1 {
+        return None;
+    }
+    Some(image::imageops::resize(child, w, h, FilterType::Triangle))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use image::{GrayImage, Luma};
+
+    fn tmp(file: &str) -> String {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        std::env::temp_dir()
+            .join(format!("vt_search_{file}_{nanos}.png"))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn screen() -> GrayImage {
+        // Light background with a dark "icon" containing a bright cross.
+        let mut img = GrayImage::from_pixel(200, 120, Luma([230]));
+        for y in 40..70 {
+            for x in 120..150 {
+                let on_cross = x == 135 || y == 55;
+                img.put_pixel(x, y, Luma([if on_cross { 250 } else { 30 }]));
+            }
+        }
+        img
+    }
 
     #[test]
-    fn search_stub_not_found() {
+    fn finds_icon_with_region_and_center() {
+        let parent = screen();
+        let child = image::imageops::crop_imm(&parent, 115, 35, 40, 40).to_image();
+        let (pp, cp) = (tmp("parent"), tmp("child"));
+        parent.save(&pp).unwrap();
+        child.save(&cp).unwrap();
+
+        let res = flex_search(SearchRequest { parent_image: pp, child_image: cp, ..Default::default() });
+        assert_eq!(res.status, "Found");
+        assert_eq!(res.total_matches, 1);
+        let m = res.matches[0];
+        assert_eq!((m.top_left_x, m.top_left_y, m.bottom_right_x, m.bottom_right_y), (115, 35, 154, 74));
+        assert_eq!(res.center, Some((135.0, 55.0)));
+        assert!(res.precision.unwrap() > 99.0);
+    }
+
+    #[test]
+    fn absent_icon_is_not_found() {
+        let parent = GrayImage::from_fn(200, 120, |x, _| Luma([(x % 256) as u8]));
+        let child = image::imageops::crop_imm(&screen(), 115, 35, 40, 40).to_image();
+        let (pp, cp) = (tmp("absent_parent"), tmp("absent_child"));
+        parent.save(&pp).unwrap();
+        child.save(&cp).unwrap();
+
+        let res = flex_search(SearchRequest { parent_image: pp.clone(), child_image: cp.clone(), ..Default::default() });
+        assert_eq!(res.status, "NotFound");
+        assert_eq!(res.total_matches, 0);
+        assert!(res.matches.is_empty());
+        assert!(res.center.is_none());
+
+        // A NaN confidence is rejected instead of accepting any hit.
+        let res = flex_search(SearchRequest { parent_image: pp, child_image: cp, min_confidence: Some(f32::NAN), ..Default::default() });
+        assert_eq!(res.status, "Error");
+        assert_eq!(res.error.map(|e| e.code), Some(CompareErrorCode::InvalidArgument));
+    }
+
+    #[test]
+    fn unreadable_images_are_errors() {
         let req = SearchRequest {
             parent_image: "parent.png".into(),
             child_image: "child.png".into(),
-            meta: Meta::default(),
+            ..Default::default()
         };
         let res = flex_search(req);
-        assert_eq!(res.status, "NotFound");
+        assert_eq!(res.status, "Error");
         assert_eq!(res.total_matches, 0);
         assert!(res.matches.is_empty());
         assert!(res.result_image_ref.is_none());
+        let error = res.error.unwrap();
+        assert_eq!(error.code, CompareErrorCode::InputMissing);
+        assert!(error.message.contains("parent.png"), "{}", error.message);
+
+        let (garbage, child) = (tmp("garbage"), tmp("garbage_child"));
+        std::fs::write(&garbage, b"not an image").unwrap();
+        screen().save(&child).unwrap();
+        let res = flex_search(SearchRequest { parent_image: garbage, child_image: child, ..Default::default() });
+        assert_eq!(res.status, "Error");
+        assert_eq!(res.error.map(|e| e.code), Some(CompareErrorCode::DecodeFailed));
+    }
+
+    /// 20x20 textured icon and a screen showing it at `scale` (nearest-neighbour) at (60, 30).
+    fn scaled_icon(scale: u32) -> (GrayImage, GrayImage) {
+        let icon = GrayImage::from_fn(20, 20, |x, y| Luma([((x / 4 * 37 + y / 4 * 91) % 200 + 30) as u8]));
+        let big = image::imageops::resize(&icon, 20 * scale, 20 * scale, FilterType::Nearest);
+        let mut parent = GrayImage::from_pixel(200, 140, Luma([230]));
+        image::imageops::replace(&mut parent, &big, 60, 30);
+        (parent, icon)
+    }
+
+    #[test]
+    fn finds_icon_across_scales() {
+        let (parent, icon) = scaled_icon(2);
+        let (pp, cp) = (tmp("scaled_parent"), tmp("scaled_child"));
+        parent.save(&pp).unwrap();
+        icon.save(&cp).unwrap();
+
+        let one_scale = flex_search(SearchRequest { parent_image: pp.clone(), child_image: cp.clone(), ..Default::default() });
+        assert_eq!(one_scale.status, "NotFound");
+
+        let req = SearchRequest {
+            parent_image: pp,
+            child_image: cp,
+            min_scale: Some(0.5),
+            max_scale: Some(3.0),
+            ..Default::default()
+        };
+        let res = flex_search(req.clone());
+        assert_eq!(res.status, "Found");
+        let m = res.matches[0];
+        assert!((m.scale - 2.0).abs() < 0.05, "scale {}", m.scale);
+        assert!(m.top_left_x.abs_diff(60) <= 1 && m.top_left_y.abs_diff(30) <= 1, "{m:?}");
+        assert!(m.bottom_right_x.abs_diff(99) <= 1 && m.bottom_right_y.abs_diff(69) <= 1, "{m:?}");
+
+        req.cancel.cancel();
+        assert_eq!(flex_search(req).status, "NotFound");
+    }
+
+    #[test]
+    fn scale_grid_spans_the_range() {
+        let grid = scale_grid(0.5, 3.0, 1.1);
+        assert_eq!(grid.first(), Some(&0.5));
+        assert_eq!(grid.last(), Some(&3.0));
+        assert!(grid.contains(&1.0));
+        assert!(grid.windows(2).all(|w| w[1] > w[0] && w[1] / w[0] <= 1.1 + 1e-4));
+        assert_eq!(scale_grid(2.0, 2.0, 1.1), vec![2.0]);
+        assert!(resize(&GrayImage::new(10, 10), 0.2, (100, 100)).is_none());
+        assert!(resize(&GrayImage::new(10, 10), 2.0, (19, 100)).is_none());
+    }
+
+    #[test]
+    fn finds_every_occurrence_above_confidence() {
+        // Three identical icons and a fourth with a scratch across it.
+        let icon = image::imageops::crop_imm(&screen(), 115, 35, 40, 40).to_image();
+        let mut parent = GrayImage::from_pixel(320, 80, Luma([230]));
+        for x in [10, 90, 170, 250] {
+            image::imageops::replace(&mut parent, &icon, x, 20);
+        }
+        for x in 255..285 {
+            for y in 28..36 {
+                parent.put_pixel(x, y, Luma([230]));
+            }
+        }
+        let (pp, cp) = (tmp("stars_parent"), tmp("stars_child"));
+        parent.save(&pp).unwrap();
+        icon.save(&cp).unwrap();
+
+        let res = flex_search(SearchRequest { parent_image: pp.clone(), child_image: cp.clone(), ..Default::default() });
+        assert_eq!(res.status, "Found");
+        assert_eq!(res.total_matches, 3);
+        let mut xs: Vec<_> = res.matches.iter().map(|m| m.top_left_x).collect();
+        xs.sort();
+        assert_eq!(xs, vec![10, 90, 170]);
+        assert!(res.matches.iter().all(|m| m.score > 99.0 && m.scale == 1.0));
+        assert_eq!(res.precision, Some(res.matches[0].score));
+
+        // Allowing any overlap still reports the best match once.
+        let res = flex_search(SearchRequest { parent_image: pp.clone(), child_image: cp.clone(), max_overlap: Some(1.0), ..Default::default() });
+        let mut xs: Vec<_> = res.matches.iter().map(|m| (m.top_left_x, m.top_left_y)).collect();
+        xs.sort();
+        xs.dedup();
+        assert_eq!(xs.len(), res.matches.len());
+        assert_eq!(res.total_matches as usize, res.matches.len());
+
+        let res = flex_search(SearchRequest { parent_image: pp, child_image: cp, min_confidence: Some(50.0), ..Default::default() });
+        assert_eq!(res.total_matches, 4);
+        let last = res.matches[3];
+        assert_eq!((last.top_left_x, last.top_left_y), (250, 20));
+        assert!(last.score < 90.0 && last.score >= 50.0, "score {}", last.score);
+    }
+
+    #[test]
+    fn suppression_keeps_the_strongest_of_overlapping_hits() {
+        let hit = |x, score| Hit { x, y: 0, width: 10, height: 10, score };
+        let kept = suppress_overlaps(vec![hit(0, 0.8), hit(2, 0.95), hit(6, 0.9), hit(20, 0.7)], 0.3);
+        let xs: Vec<_> = kept.iter().map(|h| h.x).collect();
+        assert_eq!(xs, vec![2, 20]);
+        assert_eq!(overlap(&hit(0, 1.0), &hit(5, 1.0)), 50.0 / 150.0);
+        assert_eq!(overlap(&hit(0, 1.0), &hit(10, 1.0)), 0.0);
+        // With no limit every distinct hit survives, but a repeated placement does not.
+        assert_eq!(suppress_overlaps(vec![hit(0, 0.8), hit(1, 0.9)], 1.0).len(), 2);
+        assert_eq!(suppress_overlaps(vec![hit(0, 0.9), hit(1, 0.8), hit(0, 0.9)], 1.0).len(), 2);
+    }
+
+    #[test]
+    fn huge_and_non_finite_scales_stay_bounded() {
+        // The 2x icon at (10, 5) of a 60x50 screen.
+        let (screen, icon) = scaled_icon(2);
+        let parent = image::imageops::crop_imm(&screen, 50, 25, 60, 50).to_image();
+        let (pp, cp) = (tmp("bounded_parent"), tmp("bounded_child"));
+        parent.save(&pp).unwrap();
+        icon.save(&cp).unwrap();
+        let search = |min_scale, max_scale, scale_step| {
+            flex_search(SearchRequest {
+                parent_image: pp.clone(),
+                child_image: cp.clone(),
+                min_scale,
+                max_scale,
+                scale_step,
+                ..Default::default()
+            })
+        };
+
+        // Clamped to the largest scale that fits (2.5x for a 20px icon in 60x50).
+        let res = search(Some(1.0), Some(1e30), None);
+        assert_eq!(res.status, "Found");
+        assert!((res.matches[0].scale - 2.0).abs() < 0.05);
+        // Rejected (JSON 1e39 is infinite as f32).
+        let invalid = |res: SearchResult| res.status == "Error" && res.error.map(|e| e.code) == Some(CompareErrorCode::InvalidArgument);
+        for max in [f32::INFINITY, 1e39_f64 as f32, f32::NAN, 0.0] {
+            assert!(invalid(search(Some(1.0), Some(max), None)), "max {max}");
+        }
+        assert!(invalid(search(Some(f32::NAN), Some(f32::NAN), Some(f32::NAN))));
+        assert!(invalid(search(Some(f32::NEG_INFINITY), Some(3.0), None)));
+        assert!(invalid(search(Some(1.0), Some(3.0), Some(1.0))));
+        // A vanishing step is raised to the minimum ratio.
+        assert_eq!(search(Some(1.95), Some(2.05), Some(1.000_000_1)).status, "Found");
+        // No scale of the range fits.
+        assert_eq!(search(Some(3.0), Some(9.0), None).status, "NotFound");
     }
 }
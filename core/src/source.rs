@@ -0,0 +1,519 @@
+//! Where images come from: local paths, `file://` and `http(s)://` URLs, or
+//! in-memory buffers.
+//!
+//! Remote images are downloaded by the installed `Fetcher` (plain HTTP(S) by
+//! default) and optionally cached on disk, keyed by URL. A cached download is
+//! reused until it is older than the cache TTL (one hour by default), then
+//! downloaded again. Deleting the cache directory, or the entry for one URL,
+//! invalidates it at once.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use image::DynamicImage;
+
+use crate::compare::{CompareError, CompareErrorCode};
+
+/// Used when a request sets no `fetch_timeout_ms`.
+pub(crate) const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// Used when a request sets no `fetch_cache_ttl_secs`.
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+/// Largest response body `HttpFetcher` accepts.
+const MAX_DOWNLOAD_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Image pixels handed over directly instead of by path.
+#[derive(Clone)]
+pub enum ImageData {
+    /// Encoded PNG, JPEG, BMP or GIF bytes.
+    Encoded(Vec<u8>),
+    /// Raw 8-bit RGBA pixels. `stride` is the number of bytes per row and
+    /// must be at least `width * 4`; padding bytes are ignored.
+    Rgba { width: u32, height: u32, stride: u32, pixels: Vec<u8> },
+}
+
+impl std::fmt::Debug for ImageData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageData::Encoded(bytes) => write!(f, "Encoded({} bytes)", bytes.len()),
+            ImageData::Rgba { width, height, stride, .. } => write!(f, "Rgba({width}x{height}, stride {stride})"),
+        }
+    }
+}
+
+impl ImageData {
+    pub(crate) fn decode(&self) -> Result<DynamicImage, String> {
+        match self {
+            ImageData::Encoded(bytes) => image::load_from_memory(bytes).map_err(|e| e.to_string()),
+            ImageData::Rgba { width, height, stride, pixels } => {
+                let (w, h, stride) = (*width as usize, *height as usize, *stride as usize);
+                if w == 0 || h == 0 {
+                    return Err(format!("empty {width}x{height} RGBA buffer"));
+                }
+                let row = w.checked_mul(4).ok_or("RGBA width is too large")?;
+                if stride < row {
+                    return Err(format!("stride {stride} is smaller than width * 4 ({row})"));
+                }
+                let needed = stride
+                    .checked_mul(h - 1)
+                    .and_then(|n| n.checked_add(row))
+                    .ok_or_else(|| format!("{width}x{height} RGBA with stride {stride} is too large"))?;
+                if pixels.len() < needed {
+                    return Err(format!("RGBA buffer has {} bytes, {width}x{height} with stride {stride} needs {needed}", pixels.len()));
+                }
+                let packed: Vec<u8> = if stride == row {
+                    pixels[..row * h].to_vec()
+                } else {
+                    (0..h).flat_map(|y| &pixels[y * stride..y * stride + row]).copied().collect()
+                };
+                image::RgbaImage::from_raw(*width, *height, packed)
+                    .map(DynamicImage::ImageRgba8)
+                    .ok_or_else(|| "invalid RGBA buffer".to_string())
+            }
+        }
+    }
+}
+
+/// Downloads remote images. Install one with `set_fetcher`, e.g. to add
+/// authentication headers for an artifact server.
+pub trait Fetcher: Send + Sync {
+    /// Fetches an `http://` or `https://` URL, giving up after `timeout`.
+    fn fetch(&self, url: &str, timeout: Duration) -> Result<Vec<u8>, FetchError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchError {
+    /// The server has no such resource; reported like a missing file.
+    NotFound(String),
+    /// Network failure, timeout or any other unusable response.
+    Failed(String),
+}
+
+/// The default `Fetcher`: a plain GET that follows redirects.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HttpFetcher;
+
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, url: &str, timeout: Duration) -> Result<Vec<u8>, FetchError> {
+        let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+        let response = match agent.get(url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status @ (404 | 410), _)) => return Err(FetchError::NotFound(format!("HTTP {status}"))),
+            Err(ureq::Error::Status(status, _)) => return Err(FetchError::Failed(format!("HTTP {status}"))),
+            Err(e) => return Err(FetchError::Failed(e.to_string())),
+        };
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_DOWNLOAD_BYTES + 1)
+            .read_to_end(&mut body)
+            .map_err(|e| FetchError::Failed(e.to_string()))?;
+        if body.len() as u64 > MAX_DOWNLOAD_BYTES {
+            return Err(FetchError::Failed(format!("response is larger than {MAX_DOWNLOAD_BYTES} bytes")));
+        }
+        Ok(body)
+    }
+}
+
+static FETCHER: RwLock<Option<Arc<dyn Fetcher>>> = RwLock::new(None);
+
+/// Installs the fetcher used for every remote image in this process;
+/// `None` restores `HttpFetcher`.
+pub fn set_fetcher(fetcher: Option<Arc<dyn Fetcher>>) {
+    *FETCHER.write().unwrap_or_else(|e| e.into_inner()) = fetcher;
+}
+
+fn fetcher() -> Arc<dyn Fetcher> {
+    FETCHER.read().unwrap_or_else(|e| e.into_inner()).clone().unwrap_or_else(|| Arc::new(HttpFetcher))
+}
+
+/// Per-request download settings.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FetchSettings {
+    pub timeout: Option<Duration>,
+    /// Directory of cached downloads; nothing is cached when unset.
+    pub cache_dir: Option<PathBuf>,
+    /// How long a cached download is reused before it is fetched again.
+    pub cache_ttl: Option<Duration>,
+}
+
+impl FetchSettings {
+    /// Settings from the `fetch_*` fields shared by the request types.
+    pub(crate) fn new(timeout_ms: Option<u64>, cache_dir: Option<&str>, cache_ttl_secs: Option<u64>) -> Self {
+        FetchSettings {
+            timeout: timeout_ms.map(Duration::from_millis),
+            cache_dir: cache_dir.map(PathBuf::from),
+            cache_ttl: cache_ttl_secs.map(Duration::from_secs),
+        }
+    }
+}
+
+/// Why `open` could not produce an image.
+#[derive(Debug)]
+pub(crate) enum OpenError {
+    /// No file at the path, or the server has no such resource.
+    Missing,
+    /// The download failed.
+    Fetch(String),
+    /// The bytes are not a supported image.
+    Decode(String),
+}
+
+/// Opens a local path, `file://` URL or `http(s)://` URL.
+pub(crate) fn open(location: &str, settings: &FetchSettings) -> Result<DynamicImage, OpenError> {
+    if !is_remote(location) {
+        let path = local_path(location).unwrap_or_default();
+        if location.is_empty() || !path.exists() {
+            return Err(OpenError::Missing);
+        }
+        return image::open(path).map_err(|e| OpenError::Decode(e.to_string()));
+    }
+
+    let cached = settings.cache_dir.as_ref().map(|dir| dir.join(cache_key(location)));
+    let ttl = settings.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL);
+    if let Some(img) = cached
+        .as_ref()
+        .filter(|path| is_fresh(path, ttl))
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|b| image::load_from_memory(&b).ok())
+    {
+        return Ok(img);
+    }
+    let timeout = settings.timeout.unwrap_or(DEFAULT_FETCH_TIMEOUT);
+    let bytes = fetcher().fetch(location, timeout).map_err(|e| match e {
+        FetchError::NotFound(_) => OpenError::Missing,
+        FetchError::Failed(message) => OpenError::Fetch(message),
+    })?;
+    let img = image::load_from_memory(&bytes).map_err(|e| OpenError::Decode(e.to_string()))?;
+    if let Some(path) = cached {
+        // A failed cache write only costs a download next time.
+        let _ = write_atomically(&path, &bytes);
+    }
+    Ok(img)
+}
+
+/// The error search and locate report when `location` cannot be opened.
+pub(crate) fn open_error(location: &str, err: OpenError) -> CompareError {
+    let (code, message) = match err {
+        OpenError::Missing => (CompareErrorCode::InputMissing, format!("image not found: {location}")),
+        OpenError::Fetch(e) => (CompareErrorCode::FetchFailed, format!("{location}: {e}")),
+        OpenError::Decode(e) => (CompareErrorCode::DecodeFailed, format!("{location}: {e}")),
+    };
+    CompareError { code, message }
+}
+
+pub(crate) fn is_remote(location: &str) -> bool {
+    has_scheme(location, "http://") || has_scheme(location, "https://")
+}
+
+/// The file a plain path or `file://` URL points at; `None` for remote URLs.
+pub(crate) fn local_path(location: &str) -> Option<PathBuf> {
+    if is_remote(location) {
+        return None;
+    }
+    if !has_scheme(location, "file://") {
+        return Some(PathBuf::from(location));
+    }
+    let rest = &location["file://".len()..];
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    let mut path = percent_decode(rest);
+    // `file:///C:/dir` names `C:/dir` on Windows.
+    if path.as_bytes().get(2) == Some(&b':') && path.starts_with('/') {
+        path.remove(0);
+    }
+    Some(PathBuf::from(path))
+}
+
+fn has_scheme(location: &str, scheme: &str) -> bool {
+    location.get(..scheme.len()).is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// FNV-1a of the URL; stable across runs and platforms.
+fn cache_key(url: &str) -> String {
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+    format!("{hash:016x}.img")
+}
+
+/// Whether the file at `path` was written less than `ttl` ago.
+fn is_fresh(path: &Path, ttl: Duration) -> bool {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < ttl)
+}
+
+fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("tmp{}", crate::compare::nano_ts()));
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// Tests that talk HTTP, since `set_fetcher` is process-wide.
+    static HTTP: Mutex<()> = Mutex::new(());
+
+    fn png(value: u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        DynamicImage::ImageLuma8(image::GrayImage::from_pixel(8, 8, image::Luma([value])))
+            .write_to(&mut std::io::Cursor::new(&mut out), image::ImageOutputFormat::Png)
+            .unwrap();
+        out
+    }
+
+    /// Local stand-in for an artifact server: `/a.png` and `/b.png` are images,
+    /// `/garbage` is not, `/slow` answers after two seconds, anything else is
+    /// a 404. Returns the base URL and the number of requests served.
+    fn serve() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                counter.fetch_add(1, Ordering::SeqCst);
+                std::thread::spawn(move || {
+                    let mut head = Vec::new();
+                    let mut byte = [0u8];
+                    while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+                        head.push(byte[0]);
+                    }
+                    let head = String::from_utf8_lossy(&head);
+                    let path = head.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    let (status, body) = match path.as_str() {
+                        "/a.png" => ("200 OK", png(10)),
+                        "/b.png" => ("200 OK", png(250)),
+                        "/garbage" => ("200 OK", b"not an image".to_vec()),
+                        "/slow" => {
+                            std::thread::sleep(Duration::from_secs(2));
+                            ("200 OK", png(10))
+                        }
+                        _ => ("404 Not Found", vec![]),
+                    };
+                    let _ = write!(stream, "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+                    let _ = stream.write_all(&body);
+                });
+            }
+        });
+        (base, hits)
+    }
+
+    #[test]
+    fn parses_file_urls_and_paths() {
+        assert_eq!(local_path("/tmp/a b.png"), Some(PathBuf::from("/tmp/a b.png")));
+        assert_eq!(local_path("file:///tmp/a%20b.png"), Some(PathBuf::from("/tmp/a b.png")));
+        assert_eq!(local_path("FILE://localhost/tmp/x.png"), Some(PathBuf::from("/tmp/x.png")));
+        assert_eq!(local_path("file:///C:/shots/x.png"), Some(PathBuf::from("C:/shots/x.png")));
+        assert_eq!(local_path("https://example.com/x.png"), None);
+        assert!(is_remote("HTTP://example.com") && !is_remote("httpdocs/x.png"));
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(cache_key("http://a"), cache_key("http://a"));
+        assert_ne!(cache_key("http://a"), cache_key("http://b"));
+    }
+
+    #[test]
+    fn opens_file_urls() {
+        let path = std::env::temp_dir().join(format!("vt source {}.png", crate::compare::nano_ts()));
+        std::fs::write(&path, png(7)).unwrap();
+        let url = format!("file://{}", path.to_string_lossy().replace(' ', "%20"));
+        let img = open(&url, &FetchSettings::default()).unwrap();
+        assert_eq!(img.to_luma8().get_pixel(0, 0).0, [7]);
+        assert!(matches!(open("file:///nonexistent/vt.png", &FetchSettings::default()), Err(OpenError::Missing)));
+    }
+
+    #[test]
+    fn downloads_with_cache_and_timeout() {
+        let _serial = HTTP.lock().unwrap_or_else(|e| e.into_inner());
+        let (base, hits) = serve();
+        let cache = std::env::temp_dir().join(format!("vt_fetch_cache_{}", crate::compare::nano_ts()));
+        let settings = FetchSettings { timeout: Some(Duration::from_millis(500)), cache_dir: Some(cache.clone()), cache_ttl: None };
+
+        let url = format!("{base}/a.png");
+        assert_eq!(open(&url, &settings).unwrap().to_luma8().get_pixel(0, 0).0, [10]);
+        assert_eq!(open(&url, &settings).unwrap().to_luma8().get_pixel(0, 0).0, [10]);
+        assert_eq!(hits.load(Ordering::SeqCst), 1, "second open is served from the cache");
+        assert!(cache.join(cache_key(&url)).is_file());
+
+        // A corrupt cache entry is replaced by a fresh download.
+        std::fs::write(cache.join(cache_key(&url)), b"junk").unwrap();
+        assert!(open(&url, &settings).is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // An expired entry is downloaded again.
+        let expired = FetchSettings { cache_ttl: Some(Duration::ZERO), ..settings.clone() };
+        assert!(open(&url, &expired).is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert!(open(&url, &settings).is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 3, "the refreshed entry is reused");
+
+        assert!(matches!(open(&format!("{base}/missing.png"), &settings), Err(OpenError::Missing)));
+        assert!(matches!(open(&format!("{base}/garbage"), &settings), Err(OpenError::Decode(_))));
+        assert!(matches!(open(&format!("{base}/slow"), &settings), Err(OpenError::Fetch(_))));
+        assert!(!cache.join(cache_key(&format!("{base}/garbage"))).exists());
+    }
+
+    #[test]
+    fn compares_remote_images() {
+        use crate::compare::{compare_images, CompareErrorCode, CompareRequest, CompareStatus};
+        let _serial = HTTP.lock().unwrap_or_else(|e| e.into_inner());
+        let (base, _) = serve();
+        let req = |baseline: &str, input: &str| CompareRequest {
+            baseline_image: format!("{base}{baseline}"),
+            input_image: format!("{base}{input}"),
+            min_similarity: Some(99),
+            write_diff: Some(false),
+            fetch_timeout_ms: Some(500),
+            ..Default::default()
+        };
+        assert!(matches!(compare_images(req("/a.png", "/a.png")).status, Some(CompareStatus::Passed)));
+        assert!(matches!(compare_images(req("/a.png", "/b.png")).status, Some(CompareStatus::Failed)));
+        let err = |res: crate::compare::CompareResult| res.error.map(|e| e.code);
+        assert_eq!(err(compare_images(req("/gone.png", "/a.png"))), Some(CompareErrorCode::BaselineMissing));
+        assert_eq!(err(compare_images(req("/a.png", "/slow"))), Some(CompareErrorCode::FetchFailed));
+        assert_eq!(err(compare_images(req("/a.png", "/garbage"))), Some(CompareErrorCode::DecodeFailed));
+
+        let mut record = req("/a.png", "/a.png");
+        record.meta.test_mode = Some("record".into());
+        assert_eq!(err(compare_images(record)), Some(CompareErrorCode::BaselineWriteFailed));
+    }
+
+    #[test]
+    fn search_and_locate_report_unusable_downloads() {
+        use crate::locate::{flex_locate, LocateRequest};
+        use crate::search::{flex_search, SearchRequest};
+        let _serial = HTTP.lock().unwrap_or_else(|e| e.into_inner());
+        let (base, _) = serve();
+        let search = |child: &str| {
+            flex_search(SearchRequest {
+                parent_image: format!("{base}/a.png"),
+                child_image: format!("{base}{child}"),
+                fetch_timeout_ms: Some(500),
+                ..Default::default()
+            })
+        };
+        assert_eq!(search("/a.png").status, "Found");
+        let gone = search("/gone.png");
+        assert_eq!(gone.status, "Error");
+        assert_eq!(gone.error.map(|e| e.code), Some(CompareErrorCode::InputMissing));
+        assert_eq!(search("/garbage").error.map(|e| e.code), Some(CompareErrorCode::DecodeFailed));
+        let slow = search("/slow");
+        assert_eq!(slow.status, "Error");
+        assert_eq!(slow.error.map(|e| e.code), Some(CompareErrorCode::FetchFailed));
+
+        let locate = |relative: &str| {
+            flex_locate(LocateRequest {
+                container_image: format!("{base}/a.png"),
+                main_image: format!("{base}/a.png"),
+                relative_image: format!("{base}{relative}"),
+                fetch_timeout_ms: Some(500),
+                ..Default::default()
+            })
+        };
+        assert_eq!(locate("/gone.png").error.map(|e| e.code), Some(CompareErrorCode::InputMissing));
+        let slow = locate("/slow");
+        assert_eq!(slow.status, "Error");
+        let error = slow.error.unwrap();
+        assert_eq!(error.code, CompareErrorCode::FetchFailed);
+        assert!(error.message.starts_with(&format!("{base}/slow: ")), "{}", error.message);
+    }
+
+    #[test]
+    fn html_reports_embed_remote_images() {
+        use crate::compare::{compare_images, CompareRequest};
+        use crate::html_report::{render, ReportEntry};
+        let _serial = HTTP.lock().unwrap_or_else(|e| e.into_inner());
+        let (base, _) = serve();
+        let request = CompareRequest {
+            baseline_image: format!("{base}/a.png"),
+            input_image: format!("{base}/b.png"),
+            fetch_timeout_ms: Some(500),
+            write_diff: Some(false),
+            ..Default::default()
+        };
+        let entry = ReportEntry { result: compare_images(request.clone()), request };
+        let html = render("remote", &[entry]);
+        assert_eq!(html.matches("data:image/png;base64,").count(), 2);
+        assert!(!html.contains("not available"));
+    }
+
+    struct Stub;
+
+    impl Fetcher for Stub {
+        fn fetch(&self, url: &str, _timeout: Duration) -> Result<Vec<u8>, FetchError> {
+            match url {
+                "https://artifacts.example/base.png" => Ok(png(99)),
+                _ => Err(FetchError::Failed("denied".into())),
+            }
+        }
+    }
+
+    #[test]
+    fn custom_fetcher_replaces_http() {
+        let _serial = HTTP.lock().unwrap_or_else(|e| e.into_inner());
+        set_fetcher(Some(Arc::new(Stub)));
+        let found = open("https://artifacts.example/base.png", &FetchSettings::default());
+        let denied = open("https://artifacts.example/other.png", &FetchSettings::default());
+        set_fetcher(None);
+        assert_eq!(found.unwrap().to_luma8().get_pixel(0, 0).0, [99]);
+        assert!(matches!(denied, Err(OpenError::Fetch(m)) if m == "denied"));
+    }
+
+    #[test]
+    fn rgba_rows_skip_stride_padding() {
+        // 2x2 pixels, 12 bytes per row: 8 pixel bytes then 4 bytes of padding.
+        let mut pixels = vec![0u8; 24];
+        pixels[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        pixels[8..12].copy_from_slice(&[99; 4]);
+        pixels[12..20].copy_from_slice(&[9, 10, 11, 12, 13, 14, 15, 16]);
+        let img = ImageData::Rgba { width: 2, height: 2, stride: 12, pixels }.decode().unwrap().to_rgba8();
+        assert_eq!(img.get_pixel(1, 0).0, [5, 6, 7, 8]);
+        assert_eq!(img.get_pixel(0, 1).0, [9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn rejects_bad_geometry_and_bytes() {
+        let rgba = |width, height, stride, len| ImageData::Rgba { width, height, stride, pixels: vec![0; len] };
+        assert!(rgba(0, 1, 0, 0).decode().is_err());
+        assert!(rgba(2, 2, 7, 16).decode().unwrap_err().contains("stride"));
+        assert!(rgba(2, 2, 8, 15).decode().unwrap_err().contains("needs 16"));
+        assert!(rgba(u32::MAX, 2, u32::MAX, 16).decode().is_err());
+        assert!(rgba(1, u32::MAX, u32::MAX, 16).decode().is_err());
+        // The last row needs no padding.
+        assert!(rgba(2, 2, 12, 20).decode().is_ok());
+        assert!(ImageData::Encoded(b"not an image".to_vec()).decode().is_err());
+        assert_eq!(format!("{:?}", rgba(2, 2, 8, 16)), "Rgba(2x2, stride 8)");
+    }
+}
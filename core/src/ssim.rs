@@ -0,0 +1,208 @@
+//! Structural similarity (SSIM) and multi-scale SSIM over luminance planes.
+//!
+//! Follows Wang et al.: 11-tap Gaussian window with sigma 1.5 and the usual
+//! stabilizing constants for 8-bit data. Borders are handled by clamping.
+//! Pixels whose mask entry is `false` do not contribute to the mean.
+
+const K1: f32 = 0.01;
+const K2: f32 = 0.03;
+const L: f32 = 255.0;
+const WINDOW_RADIUS: usize = 5;
+const SIGMA: f32 = 1.5;
+/// Per-scale exponents from the MS-SSIM paper, finest scale first.
+const MS_WEIGHTS: [f32; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+/// Coarsest scale must keep at least this many pixels per side.
+const MS_MIN_SIDE: usize = 16;
+
+/// A single-channel float image.
+#[derive(Debug, Clone)]
+pub(crate) struct Plane {
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<f32>,
+}
+
+impl Plane {
+    pub fn from_luma(img: &image::GrayImage) -> Self {
+        Plane {
+            width: img.width() as usize,
+            height: img.height() as usize,
+            values: img.as_raw().iter().map(|&v| v as f32).collect(),
+        }
+    }
+
+    /// Halves both dimensions by averaging 2x2 blocks.
+    fn downsample(&self) -> Plane {
+        let (w, h) = (self.width / 2, self.height / 2);
+        let mut values = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                let at = |dx: usize, dy: usize| self.values[(2 * y + dy) * self.width + 2 * x + dx];
+                values.push((at(0, 0) + at(1, 0) + at(0, 1) + at(1, 1)) / 4.0);
+            }
+        }
+        Plane { width: w, height: h, values }
+    }
+}
+
+/// Mean SSIM in `[-1, 1]` over the included pixels (1.0 when nothing is included).
+pub(crate) fn ssim(a: &Plane, b: &Plane, mask: &[bool]) -> f32 {
+    let (l, cs) = ssim_components(a, b);
+    mean_masked(l.iter().zip(&cs).map(|(l, c)| l * c), mask)
+}
+
+/// Multi-scale SSIM in `[0, 1]`. Uses up to five scales, fewer for small
+/// images, renormalizing the scale weights accordingly.
+pub(crate) fn ms_ssim(a: &Plane, b: &Plane, mask: &[bool]) -> f32 {
+    let mut scales = 1;
+    let (mut w, mut h) = (a.width, a.height);
+    while scales < MS_WEIGHTS.len() && w / 2 >= MS_MIN_SIDE && h / 2 >= MS_MIN_SIDE {
+        w /= 2;
+        h /= 2;
+        scales += 1;
+    }
+    let total: f32 = MS_WEIGHTS[..scales].iter().sum();
+
+    let (mut a, mut b, mut mask) = (a.clone(), b.clone(), mask.to_vec());
+    let mut score = 1.0f32;
+    for (i, weight) in MS_WEIGHTS[..scales].iter().enumerate() {
+        let (l, cs) = ssim_components(&a, &b);
+        let value = if i + 1 == scales {
+            mean_masked(l.iter().zip(&cs).map(|(l, c)| l * c), &mask)
+        } else {
+            mean_masked(cs.iter().copied(), &mask)
+        };
+        // Negative structure terms are clamped; fractional powers of them are undefined.
+        score *= value.max(0.0).powf(weight / total);
+        if i + 1 < scales {
+            mask = downsample_mask(&mask, a.width, a.height);
+            a = a.downsample();
+            b = b.downsample();
+        }
+    }
+    score.clamp(0.0, 1.0)
+}
+
+/// Per-pixel luminance and contrast-structure terms.
+fn ssim_components(a: &Plane, b: &Plane) -> (Vec<f32>, Vec<f32>) {
+    let c1 = (K1 * L).powi(2);
+    let c2 = (K2 * L).powi(2);
+    let kernel = gaussian_kernel();
+    let blur = |v: Vec<f32>| blur(&v, a.width, a.height, &kernel);
+
+    let mu_a = blur(a.values.clone());
+    let mu_b = blur(b.values.clone());
+    let aa = blur(a.values.iter().map(|v| v * v).collect());
+    let bb = blur(b.values.iter().map(|v| v * v).collect());
+    let ab = blur(a.values.iter().zip(&b.values).map(|(x, y)| x * y).collect());
+
+    let n = a.values.len();
+    let mut lum = Vec::with_capacity(n);
+    let mut cs = Vec::with_capacity(n);
+    for i in 0..n {
+        let (ma, mb) = (mu_a[i], mu_b[i]);
+        let var_a = (aa[i] - ma * ma).max(0.0);
+        let var_b = (bb[i] - mb * mb).max(0.0);
+        let cov = ab[i] - ma * mb;
+        lum.push((2.0 * ma * mb + c1) / (ma * ma + mb * mb + c1));
+        cs.push((2.0 * cov + c2) / (var_a + var_b + c2));
+    }
+    (lum, cs)
+}
+
+fn mean_masked(values: impl Iterator<Item = f32>, mask: &[bool]) -> f32 {
+    let (mut sum, mut count) = (0.0f64, 0u64);
+    for (v, &keep) in values.zip(mask) {
+        if keep {
+            sum += v as f64;
+            count += 1;
+        }
+    }
+    if count == 0 { 1.0 } else { (sum / count as f64) as f32 }
+}
+
+/// A coarse pixel is included only when all four fine pixels are.
+fn downsample_mask(mask: &[bool], width: usize, height: usize) -> Vec<bool> {
+    let (w, h) = (width / 2, height / 2);
+    let mut out = Vec::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            let at = |dx: usize, dy: usize| mask[(2 * y + dy) * width + 2 * x + dx];
+            out.push(at(0, 0) && at(1, 0) && at(0, 1) && at(1, 1));
+        }
+    }
+    out
+}
+
+fn gaussian_kernel() -> Vec<f32> {
+    let r = WINDOW_RADIUS as i32;
+    let raw: Vec<f32> = (-r..=r)
+        .map(|i| (-((i * i) as f32) / (2.0 * SIGMA * SIGMA)).exp())
+        .collect();
+    let sum: f32 = raw.iter().sum();
+    raw.into_iter().map(|v| v / sum).collect()
+}
+
+/// Separable Gaussian blur with clamped borders.
+fn blur(src: &[f32], width: usize, height: usize, kernel: &[f32]) -> Vec<f32> {
+    let r = WINDOW_RADIUS as isize;
+    let clamp = |v: isize, max: usize| v.clamp(0, max as isize - 1) as usize;
+    let mut tmp = vec![0.0f32; src.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let mut acc = 0.0;
+            for (k, wgt) in kernel.iter().enumerate() {
+                acc += wgt * src[y * width + clamp(x as isize + k as isize - r, width)];
+            }
+            tmp[y * width + x] = acc;
+        }
+    }
+    let mut out = vec![0.0f32; src.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let mut acc = 0.0;
+            for (k, wgt) in kernel.iter().enumerate() {
+                acc += wgt * tmp[clamp(y as isize + k as isize - r, height) * width + x];
+            }
+            out[y * width + x] = acc;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plane(w: usize, h: usize, f: impl Fn(usize, usize) -> f32) -> Plane {
+        let values = (0..w * h).map(|i| f(i % w, i / w)).collect();
+        Plane { width: w, height: h, values }
+    }
+
+    #[test]
+    fn identical_planes_score_one() {
+        let a = plane(64, 64, |x, y| ((x * 7 + y * 13) % 256) as f32);
+        let mask = vec![true; 64 * 64];
+        assert!((ssim(&a, &a, &mask) - 1.0).abs() < 1e-4);
+        assert!((ms_ssim(&a, &a, &mask) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn structural_change_scores_lower_than_brightness_shift() {
+        let a = plane(64, 64, |x, _| if (x / 4) % 2 == 0 { 60.0 } else { 190.0 });
+        let shifted = plane(64, 64, |x, _| if (x / 4) % 2 == 0 { 70.0 } else { 200.0 });
+        let flat = plane(64, 64, |_, _| 125.0);
+        let mask = vec![true; 64 * 64];
+        assert!(ssim(&a, &shifted, &mask) > 0.95);
+        assert!(ssim(&a, &flat, &mask) < 0.1);
+        assert!(ms_ssim(&a, &shifted, &mask) > ms_ssim(&a, &flat, &mask));
+    }
+
+    #[test]
+    fn masked_pixels_are_ignored() {
+        let a = plane(32, 32, |_, _| 100.0);
+        let b = plane(32, 32, |x, _| if x < 16 { 100.0 } else { 0.0 });
+        let mask: Vec<bool> = (0..32 * 32).map(|i| i % 32 < 8).collect();
+        assert!((ssim(&a, &b, &mask) - 1.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,342 @@
+//! JUnit XML and JSON export of compare/search/locate outcomes for CI dashboards.
+//!
+//! Records are grouped into suites by `meta.execution_name` and named by
+//! `meta.test_name`. Outcomes:
+//! - compare: `Failed` fails, `Error` errors, anything else passes
+//! - search/locate: `Error` errors, `NotFound` fails
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compare::{CompareRequest, CompareResult, CompareStatus};
+use crate::filters::Meta;
+use crate::html_report::escape;
+use crate::locate::{LocateRequest, LocateResult};
+use crate::search::{SearchRequest, SearchResult};
+
+/// Version of the JSON layout produced by `json_report`.
+pub const SCHEMA_VERSION: u32 = 1;
+const DEFAULT_SUITE: &str = "default";
+
+/// One SDK call and its result.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "operation")]
+#[allow(clippy::large_enum_variant)] // records are few and built once
+pub enum TestRecord {
+    Compare { request: CompareRequest, result: CompareResult },
+    Search { request: SearchRequest, result: SearchResult },
+    Locate { request: LocateRequest, result: LocateResult },
+}
+
+/// Serialized like `CompareStatus`: `Passed`, `Failed`, `Error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestReport {
+    pub schema_version: u32,
+    pub summary: Summary,
+    /// Sorted by name.
+    pub suites: Vec<Suite>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub tests: u32,
+    pub passed: u32,
+    pub failed: u32,
+    pub errors: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Suite {
+    /// `meta.execution_name`, or `default`.
+    pub name: String,
+    pub summary: Summary,
+    /// In record order.
+    pub tests: Vec<TestCase>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCase {
+    pub name: String,
+    /// `Compare`, `Search` or `Locate`.
+    pub operation: &'static str,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_name: Option<String>,
+    /// The SDK result as returned.
+    pub result: serde_json::Value,
+}
+
+impl TestRecord {
+    fn meta(&self) -> &Meta {
+        match self {
+            TestRecord::Compare { request, .. } => &request.meta,
+            TestRecord::Search { request, .. } => &request.meta,
+            TestRecord::Locate { request, .. } => &request.meta,
+        }
+    }
+
+    fn test_case(&self, index: usize) -> TestCase {
+        let meta = self.meta();
+        let mut case = TestCase {
+            name: meta.test_name.clone().unwrap_or_else(|| format!("test_{}", index + 1)),
+            operation: "Compare",
+            outcome: Outcome::Passed,
+            message: None,
+            similarity: None,
+            threshold: None,
+            diff_path: None,
+            project_name: meta.project_name.clone(),
+            result: serde_json::Value::Null,
+        };
+        match self {
+            TestRecord::Compare { request, result } => {
+                case.similarity = Some(result.obtained_similarity);
+                case.threshold = request.min_similarity;
+                case.diff_path = result.result_image_ref.clone();
+                case.result = serde_json::to_value(result).unwrap_or_default();
+                match (result.status, &result.error) {
+                    (Some(CompareStatus::Error), err) => {
+                        case.outcome = Outcome::Error;
+                        case.message = err.as_ref().map(|e| format!("{:?}: {}", e.code, e.message));
+                    }
+                    (Some(CompareStatus::Failed), _) => {
+                        case.outcome = Outcome::Failed;
+                        case.message = Some(format!(
+                            "similarity {:.2} is below threshold {}",
+                            result.obtained_similarity,
+                            request.min_similarity.unwrap_or_default()
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            TestRecord::Search { request, result } => {
+                case.operation = "Search";
+                case.result = serde_json::to_value(result).unwrap_or_default();
+                if let Some(err) = &result.error {
+                    case.outcome = Outcome::Error;
+                    case.message = Some(format!("{:?}: {}", err.code, err.message));
+                } else if result.status != "Found" {
+                    case.outcome = Outcome::Failed;
+                    case.message = Some(format!("{} not found in {}", request.child_image, request.parent_image));
+                }
+            }
+            TestRecord::Locate { request, result } => {
+                case.operation = "Locate";
+                case.result = serde_json::to_value(result).unwrap_or_default();
+                if let Some(err) = &result.error {
+                    case.outcome = Outcome::Error;
+                    case.message = Some(format!("{:?}: {}", err.code, err.message));
+                } else if result.status != "Found" {
+                    case.outcome = Outcome::Failed;
+                    case.message = Some(format!(
+                        "{} or {} not found in {}",
+                        request.main_image, request.relative_image, request.container_image
+                    ));
+                }
+            }
+        }
+        case
+    }
+}
+
+impl Summary {
+    fn add(&mut self, outcome: Outcome) {
+        self.tests += 1;
+        match outcome {
+            Outcome::Passed => self.passed += 1,
+            Outcome::Failed => self.failed += 1,
+            Outcome::Error => self.errors += 1,
+        }
+    }
+}
+
+/// Groups `records` into suites.
+pub fn json_report(records: &[TestRecord]) -> TestReport {
+    let mut suites: BTreeMap<String, Suite> = BTreeMap::new();
+    let mut summary = Summary::default();
+    for (i, record) in records.iter().enumerate() {
+        let name = record.meta().execution_name.clone().unwrap_or_else(|| DEFAULT_SUITE.to_string());
+        let case = record.test_case(i);
+        summary.add(case.outcome);
+        let suite = suites
+            .entry(name.clone())
+            .or_insert_with(|| Suite { name, summary: Summary::default(), tests: vec![] });
+        suite.summary.add(case.outcome);
+        suite.tests.push(case);
+    }
+    TestReport { schema_version: SCHEMA_VERSION, summary, suites: suites.into_values().collect() }
+}
+
+/// JUnit XML. Similarity, threshold and diff path are testcase properties;
+/// the diff is also attached via `[[ATTACHMENT|path]]` in `system-out`.
+pub fn junit_xml(records: &[TestRecord]) -> String {
+    let report = json_report(records);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let s = &report.summary;
+    xml.push_str(&format!(
+        "<testsuites name=\"vt-sdk\" tests=\"{}\" failures=\"{}\" errors=\"{}\">\n",
+        s.tests, s.failed, s.errors
+    ));
+    for suite in &report.suites {
+        let s = &suite.summary;
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\">\n",
+            escape(&suite.name),
+            s.tests,
+            s.failed,
+            s.errors
+        ));
+        for case in &suite.tests {
+            let classname = match &case.project_name {
+                Some(project) => format!("{project}.{}", case.operation),
+                None => case.operation.to_string(),
+            };
+            xml.push_str(&format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"0\">\n",
+                escape(&case.name),
+                escape(&classname)
+            ));
+            let properties: Vec<(&str, String)> = [
+                ("similarity", case.similarity.map(|v| format!("{v:.2}"))),
+                ("threshold", case.threshold.map(|v| v.to_string())),
+                ("diffPath", case.diff_path.clone()),
+            ]
+            .into_iter()
+            .filter_map(|(k, v)| v.map(|v| (k, v)))
+            .collect();
+            if !properties.is_empty() {
+                xml.push_str("      <properties>\n");
+                for (name, value) in properties {
+                    xml.push_str(&format!("        <property name=\"{name}\" value=\"{}\"/>\n", escape(&value)));
+                }
+                xml.push_str("      </properties>\n");
+            }
+            let tag = match case.outcome {
+                Outcome::Passed => None,
+                Outcome::Failed => Some("failure"),
+                Outcome::Error => Some("error"),
+            };
+            if let Some(tag) = tag {
+                let message = escape(case.message.as_deref().unwrap_or_default());
+                xml.push_str(&format!("      <{tag} message=\"{message}\" type=\"{}\">{message}</{tag}>\n", case.operation));
+            }
+            if let Some(diff) = &case.diff_path {
+                xml.push_str(&format!("      <system-out>[[ATTACHMENT|{}]]</system-out>\n", escape(diff)));
+            }
+            xml.push_str("    </testcase>\n");
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(test: &str, execution: Option<&str>) -> Meta {
+        Meta {
+            test_name: Some(test.into()),
+            execution_name: execution.map(str::to_string),
+            project_name: Some("shop".into()),
+            ..Default::default()
+        }
+    }
+
+    fn compare(test: &str, execution: Option<&str>, status: CompareStatus, similarity: f32) -> TestRecord {
+        let mut result = CompareResult::from_error(crate::CompareErrorCode::InputMissing, "input image not found: b.png");
+        result.status = Some(status);
+        result.obtained_similarity = similarity;
+        if status != CompareStatus::Error {
+            result.error = None;
+            result.result_image_ref = Some("/tmp/diff <1>.png".into());
+        }
+        TestRecord::Compare {
+            request: CompareRequest { min_similarity: Some(95), meta: meta(test, execution), ..Default::default() },
+            result,
+        }
+    }
+
+    fn records() -> Vec<TestRecord> {
+        let search: TestRecord = serde_json::from_value(serde_json::json!({
+            "operation": "Search",
+            "request": { "parentImage": "p.png", "childImage": "c.png", "meta": { "testName": "icon", "executionName": "run-2" } },
+            "result": { "status": "NotFound", "totalMatches": 0 },
+        }))
+        .unwrap();
+        vec![
+            compare("login", Some("run-2"), CompareStatus::Passed, 99.0),
+            compare("home", Some("run-2"), CompareStatus::Failed, 87.5),
+            compare("cart", Some("run-1"), CompareStatus::Error, 0.0),
+            search,
+        ]
+    }
+
+    #[test]
+    fn json_report_groups_by_execution() {
+        let report = json_report(&records());
+        assert_eq!(report.schema_version, 1);
+        assert_eq!(report.summary, Summary { tests: 4, passed: 1, failed: 2, errors: 1 });
+        let names: Vec<_> = report.suites.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["run-1", "run-2"]);
+        let home = &report.suites[1].tests[1];
+        assert_eq!((home.name.as_str(), home.outcome), ("home", Outcome::Failed));
+        assert_eq!((home.similarity, home.threshold), (Some(87.5), Some(95)));
+        assert_eq!(home.message.as_deref(), Some("similarity 87.50 is below threshold 95"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["suites"][1]["tests"][2]["operation"], "Search");
+        assert_eq!(json["suites"][1]["tests"][2]["outcome"], "Failed");
+        assert_eq!(json["suites"][1]["tests"][1]["diffPath"], "/tmp/diff <1>.png");
+        assert_eq!(json["suites"][0]["tests"][0]["result"]["error"]["code"], "InputMissing");
+
+        // A search or locate that could not download its images errors rather than fails.
+        let locate: TestRecord = serde_json::from_value(serde_json::json!({
+            "operation": "Locate",
+            "request": { "containerImage": "https://cdn/c.png", "mainImage": "m.png", "relativeImage": "r.png", "meta": { "testName": "badge" } },
+            "result": { "status": "Error", "error": { "code": "FetchFailed", "message": "https://cdn/c.png: timed out" } },
+        }))
+        .unwrap();
+        let badge = &json_report(&[locate]).suites[0].tests[0];
+        assert_eq!(badge.outcome, Outcome::Error);
+        assert_eq!(badge.message.as_deref(), Some("FetchFailed: https://cdn/c.png: timed out"));
+    }
+
+    #[test]
+    fn junit_xml_reports_failures_with_properties() {
+        let xml = junit_xml(&records());
+        assert!(xml.contains("<testsuites name=\"vt-sdk\" tests=\"4\" failures=\"2\" errors=\"1\">"));
+        assert!(xml.contains("<testsuite name=\"run-2\" tests=\"3\" failures=\"2\" errors=\"0\">"));
+        assert!(xml.contains("<testcase name=\"home\" classname=\"shop.Compare\" time=\"0\">"));
+        assert!(xml.contains("<property name=\"similarity\" value=\"87.50\"/>"));
+        assert!(xml.contains("<property name=\"threshold\" value=\"95\"/>"));
+        assert!(xml.contains("<property name=\"diffPath\" value=\"/tmp/diff &lt;1&gt;.png\"/>"));
+        assert!(xml.contains("<failure message=\"similarity 87.50 is below threshold 95\" type=\"Compare\">"));
+        assert!(xml.contains("<error message=\"InputMissing: input image not found: b.png\" type=\"Compare\">"));
+        assert!(xml.contains("<failure message=\"c.png not found in p.png\" type=\"Search\">"));
+        assert!(xml.contains("[[ATTACHMENT|/tmp/diff &lt;1&gt;.png]]"));
+        assert_eq!(xml.matches("<testcase ").count(), xml.matches("</testcase>").count());
+    }
+}
@@ -3,14 +3,31 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+/**
+ * Called once when a job completes or is cancelled, with the job handle, its
+ * `vt_execute` response and the `user_data` given at registration. The
+ * response string is only valid during the call; do not free it.
+ */
+typedef void (*VtJobCallback)(uint64_t job, const char *response_json, void *user_data);
+
 /**
  * Free strings returned by this library.
  */
 void vt_free_string(const char *ptr);
 
+/**
+ * The `{"code","message"}` error object of the last call made on this thread,
+ * or null if that call succeeded. Free the result with `vt_free_string`.
+ */
+const char *vt_last_error(void);
+
 /**
  * Compare images (URLs or file paths), returning a JSON string.
  * JSON fields match the acceptance criteria (obtainedSimilarity, status, resultImageRef, etc.).
+ * When the comparison cannot run, `status` is `Error` and `error.code` is one of
+ * InvalidArgument (null/non-UTF-8 strings, malformed JSON), BaselineMissing, InputMissing,
+ * DecodeFailed, DimensionMismatch, BaselineWriteFailed or FetchFailed.
+ * With `meta.testMode` "record" the input is saved to `baseline_url` and `status` is `NewBaseline`.
  */
 const char *vt_compare_images(const char *baseline_url,
                               const char *input_url,
@@ -19,6 +36,39 @@ const char *vt_compare_images(const char *baseline_url,
                               const char *excluded_areas_json,
                               const char *meta_json);
 
+/**
+ * Compare encoded image bytes (PNG, JPEG, BMP or GIF) held in memory.
+ * `options_json` is an optional `CompareRequest` with the remaining settings
+ * (minSimilarity, excludedAreas, meta, ...). A null `baseline` uses the
+ * baseline named by its `baselineImage` or `baselineRoot` instead. The buffers
+ * are copied before this returns. Returns the same JSON as `vt_compare_images`.
+ */
+const char *vt_compare_encoded(const uint8_t *baseline,
+                               uintptr_t baseline_len,
+                               const uint8_t *input,
+                               uintptr_t input_len,
+                               const char *options_json);
+
+/**
+ * Compare raw 8-bit RGBA pixels held in memory. `*_len` is the size of each
+ * buffer in bytes and `*_stride` the number of bytes per row (at least
+ * `width * 4`); padding bytes are ignored and the last row needs none, so a
+ * buffer must hold `stride * (height - 1) + width * 4` bytes. Geometry that
+ * does not fit the buffer is an InvalidArgument error; nothing beyond `*_len`
+ * is read. Otherwise behaves like `vt_compare_encoded`.
+ */
+const char *vt_compare_rgba(const uint8_t *baseline,
+                            uintptr_t baseline_len,
+                            uint32_t baseline_width,
+                            uint32_t baseline_height,
+                            uint32_t baseline_stride,
+                            const uint8_t *input,
+                            uintptr_t input_len,
+                            uint32_t input_width,
+                            uint32_t input_height,
+                            uint32_t input_stride,
+                            const char *options_json);
+
 /**
  * Search for a child image within a parent image. Returns JSON string.
  */
@@ -31,3 +81,90 @@ const char *vt_flex_locate(const char *container_url,
                            const char *main_url,
                            const char *relative_url,
                            const char *meta_json);
+
+/**
+ * Single JSON entry point. `request_json` is an envelope
+ * `{"version": 1, "operation": "Compare" | "Search" | "Locate" | "Batch", "request": {...}}`
+ * whose `request` has the same shape as the core request type. Returns
+ * `{"version": 1, "operation": ..., "result": {...}}`, or
+ * `{"version": 1, "error": {"code", "message"}}` with code InvalidRequest or UnsupportedVersion.
+ */
+const char *vt_execute(const char *request_json);
+
+/**
+ * Start a `vt_execute` request in the background and return its job handle
+ * (never 0) at once. Jobs run on a pool with one worker thread per core;
+ * further jobs wait in line as `Running`. Follow a job with `vt_job_poll` or
+ * `vt_job_on_complete`, stop it with `vt_job_cancel` and release it with
+ * `vt_job_free`. Requests that cannot be used still get a job, which
+ * completes with the same error response `vt_execute` would return.
+ * Returns 0 if no worker thread could be started; `vt_last_error` then has
+ * code Internal.
+ */
+uint64_t vt_job_submit(const char *request_json);
+
+/**
+ * Current state of a job: `{"job", "state": "Running" | "Completed" | "Cancelled", "response"}`.
+ * `response` is the `vt_execute` response once the job finished; a cancelled
+ * job reports an error with code Cancelled. Unknown handles return an
+ * InvalidArgument error.
+ */
+const char *vt_job_poll(uint64_t job);
+
+/**
+ * Register `callback` for a job, replacing any earlier one. It runs on the
+ * worker thread when the job completes, on the cancelling thread when it is
+ * cancelled, or right away on this thread if the job already finished.
+ * Returns the same JSON as `vt_job_poll`.
+ */
+const char *vt_job_on_complete(uint64_t job, VtJobCallback callback, void *user_data);
+
+/**
+ * Cancel a running job: it moves to `Cancelled` and its callback fires now.
+ * Work in progress inside core stops at its next check (between rows of a
+ * template match, scales of a search or pairs of a batch) and its result is
+ * discarded. Finished jobs are left as they are.
+ * Returns the same JSON as `vt_job_poll`.
+ */
+const char *vt_job_cancel(uint64_t job);
+
+/**
+ * Forget a job and its response; a running job is cancelled first. The
+ * handle is invalid afterwards.
+ */
+void vt_job_free(uint64_t job);
+
+/**
+ * Compare two screenshot directories, pairing files by relative path.
+ * `request_json` is a `BatchRequest`: `{"baselineDir", "inputDir", "compare": {...}, "threads"}`
+ * where `compare` holds the shared `CompareRequest` settings. Returns the aggregate report
+ * (status, passed/failed/error counts, added and missing screens, per-file results).
+ */
+const char *vt_compare_batch(const char *request_json);
+
+/**
+ * Write a self-contained HTML report to `output_path`.
+ * `entries_json` is an array of `{"request": CompareRequest, "result": CompareResult}`,
+ * i.e. each request passed to the SDK with the JSON it returned.
+ * Returns `{"path": ...}`, or `{"error":{code,message}}` with code InvalidArgument or Io.
+ */
+const char *vt_html_report(const char *entries_json, const char *title, const char *output_path);
+
+/**
+ * List the changes queued under a baseline store. Returns a JSON array.
+ * `state` is `Pending`, `Accepted`, `Rejected`, or null for all.
+ */
+const char *vt_approval_list(const char *baseline_root, const char *state);
+
+/**
+ * Approve a pending change: its candidate replaces the baseline.
+ * Returns the updated change JSON, or `{"error":{code,message}}` with code
+ * NotFound, NotPending, Io or InvalidArgument.
+ */
+const char *vt_approval_approve(const char *baseline_root, const char *change_id);
+
+/**
+ * Reject a pending change, keeping the current baseline. Same return shape
+ * as `vt_approval_approve`.
+ */
+const char *vt_approval_reject(const char *baseline_root, const char *change_id);
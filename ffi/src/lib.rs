@@ -1,7 +1,14 @@
 //! FFI layer exposing C ABI for the SDK.
 
-use std::ffi::{CStr, CString};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{c_void, CStr, CString};
 use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, LazyLock, Mutex};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 // Ensure features are mutually exclusive and at least one is set.
@@ -14,13 +21,18 @@ compile_error!("either feature 'real' or 'mock' must be enabled");
 #[cfg(feature = "real")]
 use core as core_crate;
 
+/// Parsed even by the mock build, which only validates it.
 #[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "mock", allow(dead_code))]
 struct FfiMeta {
     pub test_name: Option<String>,
     pub test_mode: Option<String>,
     pub project_name: Option<String>,
     pub execution_name: Option<String>,
+    pub platform: Option<String>,
+    pub locale: Option<String>,
+    pub theme: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -39,19 +51,109 @@ fn cstr_to_str<'a>(ptr: *const c_char) -> Option<&'a str> {
     s.to_str().ok()
 }
 
+thread_local! {
+    /// `{"code","message"}` of the last call on this thread that returned an error object.
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Hands `json` to the caller and records its top-level `error` object, if any,
+/// for `vt_last_error`.
 fn to_c_string(json: String) -> *const c_char {
+    let error = serde_json::from_str::<serde_json::Value>(&json)
+        .ok()
+        .and_then(|v| v.get("error").filter(|e| e.is_object()).map(|e| e.to_string()));
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
     CString::new(json).unwrap_or_else(|_| CString::new("{}").unwrap()).into_raw()
 }
 
+/// Runs an entry point body and hands its JSON to the caller. A panic is caught
+/// here instead of unwinding into the host, and becomes an `Internal` error
+/// shaped by `on_panic` like the function's other errors.
+fn guard(on_panic: fn(&str, &str) -> String, body: impl FnOnce() -> String) -> *const c_char {
+    to_c_string(catch(on_panic, body))
+}
+
+fn catch(on_panic: fn(&str, &str) -> String, body: impl FnOnce() -> String) -> String {
+    catch_unwind(AssertUnwindSafe(body))
+        .unwrap_or_else(|payload| on_panic("Internal", &format!("internal error: {}", panic_message(payload.as_ref()))))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("panic")
+}
+
+/// A required string argument.
+fn required_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, String> {
+    cstr_to_str(ptr).ok_or_else(|| format!("{name} is null or not valid UTF-8"))
+}
+
+/// An optional JSON argument; null or blank means "not provided".
+fn json_arg<T: DeserializeOwned>(ptr: *const c_char, name: &str) -> Result<Option<T>, String> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    let json = required_arg(ptr, name)?;
+    if json.trim().is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_str(json).map(Some).map_err(|e| format!("{name} is not valid JSON: {e}"))
+}
+
+/// `{"error":{"code","message"}}`, returned by every function except
+/// `vt_compare_images` when its arguments cannot be used.
+fn error_json(code: &str, message: &str) -> String {
+    serde_json::json!({ "error": { "code": code, "message": message } }).to_string()
+}
+
+/// JSON for a comparison that could not run, shaped like a core `CompareResult` error.
+fn compare_error_json(code: &str, message: &str) -> String {
+    #[cfg(feature = "real")]
+    {
+        let code = serde_json::from_value(serde_json::Value::String(code.to_string()))
+            .unwrap_or(core_crate::compare::CompareErrorCode::InvalidArgument);
+        let res = core_crate::compare::CompareResult::from_error(code, message);
+        serde_json::to_string(&res).unwrap_or_else(|_| "{}".to_string())
+    }
+    #[cfg(feature = "mock")]
+    {
+        serde_json::json!({
+            "obtainedSimilarity": 0.0,
+            "status": "Error",
+            "noiseFilter": 1,
+            "error": { "code": code, "message": message },
+        })
+        .to_string()
+    }
+}
+
 /// Free strings returned by this library.
 #[no_mangle]
 pub extern "C" fn vt_free_string(ptr: *const c_char) {
     if ptr.is_null() { return; }
-    unsafe { drop(CString::from_raw(ptr as *mut c_char)); }
+    let _ = catch_unwind(|| unsafe { drop(CString::from_raw(ptr as *mut c_char)) });
+}
+
+/// The `{"code","message"}` error object of the last call made on this thread,
+/// or null if that call succeeded. Free the result with `vt_free_string`.
+#[no_mangle]
+pub extern "C" fn vt_last_error() -> *const c_char {
+    catch_unwind(|| match LAST_ERROR.with(|last| last.borrow().clone()) {
+        Some(error) => CString::new(error).map(|c| c.into_raw() as *const c_char).unwrap_or(std::ptr::null()),
+        None => std::ptr::null(),
+    })
+    .unwrap_or(std::ptr::null())
 }
 
 /// Compare images (URLs or file paths), returning a JSON string.
 /// JSON fields match the acceptance criteria (obtainedSimilarity, status, resultImageRef, etc.).
+/// When the comparison cannot run, `status` is `Error` and `error.code` is one of
+/// InvalidArgument (null/non-UTF-8 strings, malformed JSON), BaselineMissing, InputMissing,
+/// DecodeFailed, DimensionMismatch, BaselineWriteFailed or FetchFailed.
+/// With `meta.testMode` "record" the input is saved to `baseline_url` and `status` is `NewBaseline`.
 #[no_mangle]
 pub extern "C" fn vt_compare_images(
     baseline_url: *const c_char,
@@ -61,13 +163,31 @@ pub extern "C" fn vt_compare_images(
     excluded_areas_json: *const c_char,
     meta_json: *const c_char,
 ) -> *const c_char {
-    let _baseline = match cstr_to_str(baseline_url) { Some(s) => s, None => return to_c_string("{}".to_string()) };
-    let _input = match cstr_to_str(input_url) { Some(s) => s, None => return to_c_string("{}".to_string()) };
-    let excluded_areas_ffi: Option<Vec<FfiRect>> = cstr_to_str(excluded_areas_json)
-        .and_then(|j| serde_json::from_str(j).ok());
-    let _meta_ffi: FfiMeta = cstr_to_str(meta_json)
-        .and_then(|j| serde_json::from_str(j).ok())
-        .unwrap_or_default();
+    guard(compare_error_json, || {
+        compare_images_json(baseline_url, input_url, min_similarity, noise_filter, excluded_areas_json, meta_json)
+    })
+}
+
+fn compare_images_json(
+    baseline_url: *const c_char,
+    input_url: *const c_char,
+    min_similarity: i32,
+    noise_filter: i32,
+    excluded_areas_json: *const c_char,
+    meta_json: *const c_char,
+) -> String {
+    let args = (|| {
+        Ok::<_, String>((
+            required_arg(baseline_url, "baseline_url")?,
+            required_arg(input_url, "input_url")?,
+            json_arg::<Vec<FfiRect>>(excluded_areas_json, "excluded_areas_json")?,
+            json_arg::<FfiMeta>(meta_json, "meta_json")?.unwrap_or_default(),
+        ))
+    })();
+    let (_baseline, _input, excluded_areas_ffi, _meta_ffi) = match args {
+        Ok(args) => args,
+        Err(message) => return compare_error_json("InvalidArgument", &message),
+    };
 
     #[cfg(feature = "real")]
     let result = {
@@ -82,6 +202,9 @@ pub extern "C" fn vt_compare_images(
             test_mode: _meta_ffi.test_mode,
             project_name: _meta_ffi.project_name,
             execution_name: _meta_ffi.execution_name,
+            platform: _meta_ffi.platform,
+            locale: _meta_ffi.locale,
+            theme: _meta_ffi.theme,
         };
         let req = core_crate::compare::CompareRequest {
             baseline_image: _baseline.to_string(),
@@ -90,6 +213,7 @@ pub extern "C" fn vt_compare_images(
             noise_filter: if noise_filter >= 0 { Some(noise_filter) } else { None },
             excluded_areas,
             meta,
+            ..Default::default()
         };
         let res = core_crate::compare::compare_images(req);
         serde_json::to_string(&res).unwrap_or_else(|_| "{}".to_string())
@@ -103,13 +227,151 @@ pub extern "C" fn vt_compare_images(
             "obtainedSimilarity": 42.0,
             "status": status_val,
             "resultImageRef": serde_json::Value::Null,
-            "noiseFilter": if noise_filter >= 0 { noise_filter } else { 20 },
+            "noiseFilter": if noise_filter >= 0 { noise_filter } else { 1 },
             "excludedAreas": excluded_areas_ffi.unwrap_or_default(),
         });
         json.to_string()
     };
 
-    to_c_string(result)
+    result
+}
+
+/// Compare encoded image bytes (PNG, JPEG, BMP or GIF) held in memory.
+/// `options_json` is an optional `CompareRequest` with the remaining settings
+/// (minSimilarity, excludedAreas, meta, ...). A null `baseline` uses the
+/// baseline named by its `baselineImage` or `baselineRoot` instead. The buffers
+/// are copied before this returns. Returns the same JSON as `vt_compare_images`.
+#[no_mangle]
+pub extern "C" fn vt_compare_encoded(
+    baseline: *const u8,
+    baseline_len: usize,
+    input: *const u8,
+    input_len: usize,
+    options_json: *const c_char,
+) -> *const c_char {
+    guard(compare_error_json, || {
+        let baseline = bytes_arg(baseline, baseline_len).map(|b| Buffer::Encoded(b.to_vec()));
+        let input = bytes_arg(input, input_len).map(|b| Buffer::Encoded(b.to_vec()));
+        compare_buffers_json(baseline, input, options_json)
+    })
+}
+
+/// Compare raw 8-bit RGBA pixels held in memory. `*_len` is the size of each
+/// buffer in bytes and `*_stride` the number of bytes per row (at least
+/// `width * 4`); padding bytes are ignored and the last row needs none, so a
+/// buffer must hold `stride * (height - 1) + width * 4` bytes. Geometry that
+/// does not fit the buffer is an InvalidArgument error; nothing beyond `*_len`
+/// is read. Otherwise behaves like `vt_compare_encoded`.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn vt_compare_rgba(
+    baseline: *const u8,
+    baseline_len: usize,
+    baseline_width: u32,
+    baseline_height: u32,
+    baseline_stride: u32,
+    input: *const u8,
+    input_len: usize,
+    input_width: u32,
+    input_height: u32,
+    input_stride: u32,
+    options_json: *const c_char,
+) -> *const c_char {
+    guard(compare_error_json, || {
+        let rgba = |ptr: *const u8, len: usize, width: u32, height: u32, stride: u32, name: &str| {
+            if ptr.is_null() {
+                return Ok(None);
+            }
+            let needed = rgba_len(width, height, stride).map_err(|e| format!("{name}: {e}"))?;
+            if len < needed {
+                return Err(format!("{name}: {width}x{height} with stride {stride} needs {needed} bytes, got {len}"));
+            }
+            let pixels = bytes_arg(ptr, needed).unwrap_or_default().to_vec();
+            Ok(Some(Buffer::Rgba { width, height, stride, pixels }))
+        };
+        let buffers = rgba(baseline, baseline_len, baseline_width, baseline_height, baseline_stride, "baseline")
+            .and_then(|b| Ok((b, rgba(input, input_len, input_width, input_height, input_stride, "input")?)));
+        match buffers {
+            Ok((baseline, input)) => compare_buffers_json(baseline, input, options_json),
+            Err(message) => compare_error_json("InvalidArgument", &message),
+        }
+    })
+}
+
+/// Bytes an RGBA buffer of this geometry needs, checked for overflow.
+fn rgba_len(width: u32, height: u32, stride: u32) -> Result<usize, String> {
+    if width == 0 || height == 0 {
+        return Err(format!("empty {width}x{height} image"));
+    }
+    let row = (width as usize).checked_mul(4).ok_or("width is too large")?;
+    if (stride as usize) < row {
+        return Err(format!("stride {stride} is smaller than width * 4 ({row})"));
+    }
+    (stride as usize)
+        .checked_mul(height as usize - 1)
+        .and_then(|n| n.checked_add(row))
+        .ok_or_else(|| format!("{width}x{height} with stride {stride} is too large"))
+}
+
+/// Caller-owned bytes; null means "not provided".
+fn bytes_arg<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    if ptr.is_null() {
+        return None;
+    }
+    // SAFETY: caller must provide `len` readable bytes at `ptr`
+    Some(if len == 0 { &[] } else { unsafe { std::slice::from_raw_parts(ptr, len) } })
+}
+
+/// An in-memory image copied out of the caller's buffer.
+#[cfg_attr(feature = "mock", allow(dead_code))]
+enum Buffer {
+    Encoded(Vec<u8>),
+    Rgba { width: u32, height: u32, stride: u32, pixels: Vec<u8> },
+}
+
+#[cfg(feature = "real")]
+impl From<Buffer> for core_crate::ImageData {
+    fn from(buffer: Buffer) -> Self {
+        match buffer {
+            Buffer::Encoded(bytes) => core_crate::ImageData::Encoded(bytes),
+            Buffer::Rgba { width, height, stride, pixels } => core_crate::ImageData::Rgba { width, height, stride, pixels },
+        }
+    }
+}
+
+fn compare_buffers_json(baseline: Option<Buffer>, input: Option<Buffer>, options_json: *const c_char) -> String {
+    let Some(input) = input else {
+        return compare_error_json("InvalidArgument", "input is null");
+    };
+
+    #[cfg(feature = "real")]
+    let result = {
+        let mut req = match json_arg::<core_crate::CompareRequest>(options_json, "options_json") {
+            Ok(req) => req.unwrap_or_default(),
+            Err(message) => return compare_error_json("InvalidArgument", &message),
+        };
+        req.baseline_data = baseline.map(Into::into);
+        req.input_data = Some(input.into());
+        let res = core_crate::compare::compare_images(req);
+        serde_json::to_string(&res).unwrap_or_else(|_| "{}".to_string())
+    };
+
+    #[cfg(feature = "mock")]
+    let result = {
+        let _ = (baseline, input);
+        if let Err(message) = json_arg::<serde_json::Value>(options_json, "options_json") {
+            return compare_error_json("InvalidArgument", &message);
+        }
+        serde_json::json!({
+            "obtainedSimilarity": 42.0,
+            "status": "Failed",
+            "resultImageRef": serde_json::Value::Null,
+            "noiseFilter": 1,
+        })
+        .to_string()
+    };
+
+    result
 }
 
 /// Search for a child image within a parent image. Returns JSON string.
@@ -119,11 +381,25 @@ pub extern "C" fn vt_flex_search(
     child_url: *const c_char,
     meta_json: *const c_char,
 ) -> *const c_char {
-    let _parent = match cstr_to_str(parent_url) { Some(s) => s, None => return to_c_string("{}".to_string()) };
-    let _child = match cstr_to_str(child_url) { Some(s) => s, None => return to_c_string("{}".to_string()) };
-    let _meta_ffi: FfiMeta = cstr_to_str(meta_json)
-        .and_then(|j| serde_json::from_str(j).ok())
-        .unwrap_or_default();
+    guard(error_json, || flex_search_json(parent_url, child_url, meta_json))
+}
+
+fn flex_search_json(
+    parent_url: *const c_char,
+    child_url: *const c_char,
+    meta_json: *const c_char,
+) -> String {
+    let args = (|| {
+        Ok::<_, String>((
+            required_arg(parent_url, "parent_url")?,
+            required_arg(child_url, "child_url")?,
+            json_arg::<FfiMeta>(meta_json, "meta_json")?.unwrap_or_default(),
+        ))
+    })();
+    let (_parent, _child, _meta_ffi) = match args {
+        Ok(args) => args,
+        Err(message) => return error_json("InvalidArgument", &message),
+    };
 
     #[cfg(feature = "real")]
     let result = {
@@ -132,11 +408,15 @@ pub extern "C" fn vt_flex_search(
             test_mode: _meta_ffi.test_mode,
             project_name: _meta_ffi.project_name,
             execution_name: _meta_ffi.execution_name,
+            platform: _meta_ffi.platform,
+            locale: _meta_ffi.locale,
+            theme: _meta_ffi.theme,
         };
         let req = core_crate::search::SearchRequest {
             parent_image: _parent.to_string(),
             child_image: _child.to_string(),
             meta,
+            ..Default::default()
         };
         let res = core_crate::search::flex_search(req);
         serde_json::to_string(&res).unwrap_or_else(|_| "{}".to_string())
@@ -153,7 +433,7 @@ pub extern "C" fn vt_flex_search(
         json.to_string()
     };
 
-    to_c_string(result)
+    result
 }
 
 /// Locate relative position of one element to another. Returns JSON string.
@@ -164,12 +444,27 @@ pub extern "C" fn vt_flex_locate(
     relative_url: *const c_char,
     meta_json: *const c_char,
 ) -> *const c_char {
-    let _container = match cstr_to_str(container_url) { Some(s) => s, None => return to_c_string("{}".to_string()) };
-    let _main = match cstr_to_str(main_url) { Some(s) => s, None => return to_c_string("{}".to_string()) };
-    let _relative = match cstr_to_str(relative_url) { Some(s) => s, None => return to_c_string("{}".to_string()) };
-    let _meta_ffi: FfiMeta = cstr_to_str(meta_json)
-        .and_then(|j| serde_json::from_str(j).ok())
-        .unwrap_or_default();
+    guard(error_json, || flex_locate_json(container_url, main_url, relative_url, meta_json))
+}
+
+fn flex_locate_json(
+    container_url: *const c_char,
+    main_url: *const c_char,
+    relative_url: *const c_char,
+    meta_json: *const c_char,
+) -> String {
+    let args = (|| {
+        Ok::<_, String>((
+            required_arg(container_url, "container_url")?,
+            required_arg(main_url, "main_url")?,
+            required_arg(relative_url, "relative_url")?,
+            json_arg::<FfiMeta>(meta_json, "meta_json")?.unwrap_or_default(),
+        ))
+    })();
+    let (_container, _main, _relative, _meta_ffi) = match args {
+        Ok(args) => args,
+        Err(message) => return error_json("InvalidArgument", &message),
+    };
 
     #[cfg(feature = "real")]
     let result = {
@@ -178,12 +473,16 @@ pub extern "C" fn vt_flex_locate(
             test_mode: _meta_ffi.test_mode,
             project_name: _meta_ffi.project_name,
             execution_name: _meta_ffi.execution_name,
+            platform: _meta_ffi.platform,
+            locale: _meta_ffi.locale,
+            theme: _meta_ffi.theme,
         };
         let req = core_crate::locate::LocateRequest {
             container_image: _container.to_string(),
             main_image: _main.to_string(),
             relative_image: _relative.to_string(),
             meta,
+            ..Default::default()
         };
         let res = core_crate::locate::flex_locate(req);
         serde_json::to_string(&res).unwrap_or_else(|_| "{}".to_string())
@@ -198,5 +497,817 @@ pub extern "C" fn vt_flex_locate(
         json.to_string()
     };
 
-    to_c_string(result)
+    result
+}
+
+/// Version of the `vt_execute` request/response envelope.
+const EXECUTE_VERSION: u64 = 1;
+
+/// Operations accepted by `vt_execute`; `request` deserializes straight into the core type.
+#[cfg(feature = "real")]
+#[derive(Debug, Deserialize)]
+#[serde(tag = "operation", content = "request")]
+enum Operation {
+    Compare(Box<core_crate::CompareRequest>),
+    Search(core_crate::SearchRequest),
+    Locate(core_crate::LocateRequest),
+    Batch(Box<core_crate::BatchRequest>),
+}
+
+#[cfg(feature = "real")]
+use core_crate::CancelFlag;
+
+/// Mock operations return at once, so there is nothing to stop.
+#[cfg(feature = "mock")]
+#[derive(Clone, Default)]
+struct CancelFlag;
+
+#[cfg(feature = "mock")]
+impl CancelFlag {
+    fn new() -> Self {
+        CancelFlag
+    }
+
+    fn cancel(&self) {}
+}
+
+fn execute_error_json(code: &str, message: &str) -> String {
+    serde_json::json!({ "version": EXECUTE_VERSION, "error": { "code": code, "message": message } }).to_string()
+}
+
+/// Runs a `vt_execute` request; core stops early once `_cancel` is set.
+fn execute(request_json: &str, _cancel: &CancelFlag) -> String {
+    let envelope: serde_json::Value = match serde_json::from_str(request_json) {
+        Ok(v) => v,
+        Err(e) => return execute_error_json("InvalidRequest", &format!("request is not valid JSON: {e}")),
+    };
+    match envelope.get("version").and_then(|v| v.as_u64()) {
+        Some(EXECUTE_VERSION) => {}
+        Some(v) => return execute_error_json("UnsupportedVersion", &format!("version {v} is not supported; expected {EXECUTE_VERSION}")),
+        None => return execute_error_json("InvalidRequest", "missing numeric \"version\""),
+    }
+    let name = envelope.get("operation").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+
+    #[cfg(feature = "real")]
+    let result = {
+        let operation: Operation = match serde_json::from_value(envelope) {
+            Ok(op) => op,
+            Err(e) => return execute_error_json("InvalidRequest", &format!("invalid {name} request: {e}")),
+        };
+        let cancel = _cancel.clone();
+        match operation {
+            Operation::Compare(req) => serde_json::to_value(core_crate::compare_images(core_crate::CompareRequest { cancel, ..*req })),
+            Operation::Search(req) => serde_json::to_value(core_crate::flex_search(core_crate::SearchRequest { cancel, ..req })),
+            Operation::Locate(req) => serde_json::to_value(core_crate::flex_locate(core_crate::LocateRequest { cancel, ..req })),
+            Operation::Batch(req) => serde_json::to_value(core_crate::compare_batch(core_crate::BatchRequest { cancel, ..*req })),
+        }
+        .unwrap_or_default()
+    };
+
+    #[cfg(feature = "mock")]
+    let result = match name.as_str() {
+        "Compare" => serde_json::json!({ "obtainedSimilarity": 42.0, "noiseFilter": 1 }),
+        "Search" => serde_json::json!({ "status": "NotFound", "totalMatches": 0 }),
+        "Locate" => serde_json::json!({ "status": "NotFound" }),
+        "Batch" => serde_json::json!({ "status": "Passed", "compared": 0, "passed": 0, "failed": 0, "errors": 0, "newBaselines": 0, "unchecked": 0, "added": [], "missing": [], "results": [] }),
+        _ => return execute_error_json("InvalidRequest", &format!("unknown operation {name:?}")),
+    };
+
+    serde_json::json!({ "version": EXECUTE_VERSION, "operation": name, "result": result }).to_string()
+}
+
+/// Single JSON entry point. `request_json` is an envelope
+/// `{"version": 1, "operation": "Compare" | "Search" | "Locate" | "Batch", "request": {...}}`
+/// whose `request` has the same shape as the core request type. Returns
+/// `{"version": 1, "operation": ..., "result": {...}}`, or
+/// `{"version": 1, "error": {"code", "message"}}` with code InvalidRequest or UnsupportedVersion.
+#[no_mangle]
+pub extern "C" fn vt_execute(request_json: *const c_char) -> *const c_char {
+    guard(execute_error_json, || match cstr_to_str(request_json) {
+        Some(json) => execute(json, &CancelFlag::new()),
+        None => execute_error_json("InvalidRequest", "request_json is null or not valid UTF-8"),
+    })
+}
+
+/// Called once when a job completes or is cancelled, with the job handle, its
+/// `vt_execute` response and the `user_data` given at registration. The
+/// response string is only valid during the call; do not free it.
+pub type VtJobCallback = Option<extern "C" fn(job: u64, response_json: *const c_char, user_data: *mut c_void)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+enum JobState {
+    Running,
+    Completed,
+    Cancelled,
+}
+
+struct Callback {
+    function: extern "C" fn(u64, *const c_char, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// SAFETY: `user_data` is opaque to us; the caller guarantees it may be used
+// from the worker thread that completes the job.
+unsafe impl Send for Callback {}
+
+struct Job {
+    state: JobState,
+    response: Option<String>,
+    callback: Option<Callback>,
+    /// Shared with the worker's request so core stops once the job is cancelled.
+    cancel: CancelFlag,
+}
+
+static JOBS: Mutex<Option<HashMap<u64, Arc<Mutex<Job>>>>> = Mutex::new(None);
+static NEXT_JOB: AtomicU64 = AtomicU64::new(1);
+
+fn job(id: u64) -> Option<Arc<Mutex<Job>>> {
+    JOBS.lock().unwrap_or_else(|e| e.into_inner()).as_ref()?.get(&id).cloned()
+}
+
+/// Moves a running job to `state` and fires its callback; a job that already
+/// finished (e.g. was cancelled) keeps its outcome.
+fn finish(id: u64, job: &Mutex<Job>, state: JobState, response: String) {
+    let callback = {
+        let mut job = job.lock().unwrap_or_else(|e| e.into_inner());
+        if job.state != JobState::Running {
+            return;
+        }
+        job.state = state;
+        job.response = Some(response.clone());
+        job.callback.take()
+    };
+    if let Some(callback) = callback {
+        notify(id, callback, &response);
+    }
+}
+
+fn notify(id: u64, callback: Callback, response: &str) {
+    let response = CString::new(response).unwrap_or_default();
+    (callback.function)(id, response.as_ptr(), callback.user_data);
+}
+
+/// `{"job", "state", "response"}`; `response` is present once the job finished.
+fn job_json(id: u64) -> String {
+    let Some(job) = job(id) else {
+        return error_json("InvalidArgument", &format!("unknown job {id}"));
+    };
+    let job = job.lock().unwrap_or_else(|e| e.into_inner());
+    let mut json = serde_json::json!({ "job": id, "state": job.state });
+    if let Some(response) = job.response.as_deref().and_then(|r| serde_json::from_str::<serde_json::Value>(r).ok()) {
+        json["response"] = response;
+    }
+    json.to_string()
+}
+
+/// Work queued for the job pool.
+type Task = Box<dyn FnOnce() + Send>;
+
+/// A fixed number of worker threads, started on demand, that run queued tasks
+/// in order. Submitting jobs in a loop queues them instead of creating a
+/// thread per job.
+struct Pool {
+    state: Mutex<PoolState>,
+    ready: Condvar,
+    max_workers: usize,
+    /// Starts a worker thread running the given loop.
+    spawn: fn(Task) -> std::io::Result<()>,
+}
+
+struct PoolState {
+    queue: VecDeque<Task>,
+    workers: usize,
+    idle: usize,
+}
+
+impl Pool {
+    fn new(max_workers: usize, spawn: fn(Task) -> std::io::Result<()>) -> Self {
+        Pool {
+            state: Mutex::new(PoolState { queue: VecDeque::new(), workers: 0, idle: 0 }),
+            ready: Condvar::new(),
+            max_workers: max_workers.max(1),
+            spawn,
+        }
+    }
+
+    /// Queues `task`, starting another worker when none is idle and the pool
+    /// is not full. Fails only when no worker runs and none can be started.
+    fn submit(&'static self, task: Task) -> std::io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.queue.push_back(task);
+        if state.idle > 0 || state.workers >= self.max_workers {
+            self.ready.notify_one();
+            return Ok(());
+        }
+        match (self.spawn)(Box::new(move || self.work())) {
+            Ok(()) => state.workers += 1,
+            Err(e) if state.workers == 0 => {
+                state.queue.pop_back();
+                return Err(e);
+            }
+            // The running workers will get to it.
+            Err(_) => self.ready.notify_one(),
+        }
+        Ok(())
+    }
+
+    fn work(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(task) = state.queue.pop_front() {
+                drop(state);
+                // Tasks catch their own panics; this only keeps the worker alive.
+                let _ = catch_unwind(AssertUnwindSafe(task));
+                state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                continue;
+            }
+            state.idle += 1;
+            state = self.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+            state.idle -= 1;
+        }
+    }
+}
+
+fn spawn_worker(work: Task) -> std::io::Result<()> {
+    std::thread::Builder::new().name("vt-job".to_string()).spawn(work).map(drop)
+}
+
+/// Runs every job; as many workers as the machine has cores.
+static JOB_POOL: LazyLock<Pool> =
+    LazyLock::new(|| Pool::new(std::thread::available_parallelism().map_or(1, |n| n.get()), spawn_worker));
+
+/// Start a `vt_execute` request in the background and return its job handle
+/// (never 0) at once. Jobs run on a pool with one worker thread per core;
+/// further jobs wait in line as `Running`. Follow a job with `vt_job_poll` or
+/// `vt_job_on_complete`, stop it with `vt_job_cancel` and release it with
+/// `vt_job_free`. Requests that cannot be used still get a job, which
+/// completes with the same error response `vt_execute` would return.
+/// Returns 0 if no worker thread could be started; `vt_last_error` then has
+/// code Internal.
+#[no_mangle]
+pub extern "C" fn vt_job_submit(request_json: *const c_char) -> u64 {
+    catch_unwind(|| submit_job(&JOB_POOL, cstr_to_str(request_json), execute)).unwrap_or(0)
+}
+
+/// Queues `request` on `pool` as a new job that `run` carries out.
+fn submit_job(pool: &'static Pool, request: Option<&str>, run: fn(&str, &CancelFlag) -> String) -> u64 {
+    let request = request.map(str::to_string);
+    let id = NEXT_JOB.fetch_add(1, Ordering::Relaxed);
+    let cancel = CancelFlag::new();
+    let job = Arc::new(Mutex::new(Job { state: JobState::Running, response: None, callback: None, cancel: cancel.clone() }));
+    JOBS.lock().unwrap_or_else(|e| e.into_inner()).get_or_insert_with(HashMap::new).insert(id, job.clone());
+    let submitted = pool.submit(Box::new(move || {
+        // A job cancelled before it started is not run at all.
+        if job.lock().unwrap_or_else(|e| e.into_inner()).state != JobState::Running {
+            return;
+        }
+        let response = catch(execute_error_json, || match request {
+            Some(json) => run(&json, &cancel),
+            None => execute_error_json("InvalidRequest", "request_json is null or not valid UTF-8"),
+        });
+        finish(id, &job, JobState::Completed, response);
+    }));
+    let error = submitted.err().map(|e| {
+        if let Some(jobs) = JOBS.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            jobs.remove(&id);
+        }
+        serde_json::json!({ "code": "Internal", "message": format!("could not start a job worker: {e}") }).to_string()
+    });
+    let id = if error.is_some() { 0 } else { id };
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+    id
+}
+
+/// Current state of a job: `{"job", "state": "Running" | "Completed" | "Cancelled", "response"}`.
+/// `response` is the `vt_execute` response once the job finished; a cancelled
+/// job reports an error with code Cancelled. Unknown handles return an
+/// InvalidArgument error.
+#[no_mangle]
+pub extern "C" fn vt_job_poll(job: u64) -> *const c_char {
+    guard(error_json, || job_json(job))
+}
+
+/// Register `callback` for a job, replacing any earlier one. It runs on the
+/// worker thread when the job completes, on the cancelling thread when it is
+/// cancelled, or right away on this thread if the job already finished.
+/// Returns the same JSON as `vt_job_poll`.
+#[no_mangle]
+pub extern "C" fn vt_job_on_complete(job: u64, callback: VtJobCallback, user_data: *mut c_void) -> *const c_char {
+    guard(error_json, || {
+        let Some(function) = callback else {
+            return error_json("InvalidArgument", "callback is null");
+        };
+        let Some(entry) = self::job(job) else {
+            return job_json(job);
+        };
+        let callback = Callback { function, user_data };
+        let finished = {
+            let mut entry = entry.lock().unwrap_or_else(|e| e.into_inner());
+            match (&entry.state, &entry.response) {
+                (JobState::Running, _) => {
+                    entry.callback = Some(callback);
+                    None
+                }
+                (_, response) => Some((callback, response.clone().unwrap_or_default())),
+            }
+        };
+        if let Some((callback, response)) = finished {
+            notify(job, callback, &response);
+        }
+        job_json(job)
+    })
+}
+
+/// Cancel a running job: it moves to `Cancelled` and its callback fires now.
+/// Work in progress inside core stops at its next check (between rows of a
+/// template match, scales of a search or pairs of a batch) and its result is
+/// discarded. Finished jobs are left as they are.
+/// Returns the same JSON as `vt_job_poll`.
+#[no_mangle]
+pub extern "C" fn vt_job_cancel(job: u64) -> *const c_char {
+    guard(error_json, || {
+        if let Some(entry) = self::job(job) {
+            entry.lock().unwrap_or_else(|e| e.into_inner()).cancel.cancel();
+            finish(job, &entry, JobState::Cancelled, execute_error_json("Cancelled", &format!("job {job} was cancelled")));
+        }
+        job_json(job)
+    })
+}
+
+/// Forget a job and its response; a running job is cancelled first. The
+/// handle is invalid afterwards.
+#[no_mangle]
+pub extern "C" fn vt_job_free(job: u64) {
+    let _ = catch_unwind(|| {
+        vt_free_string(vt_job_cancel(job));
+        if let Some(jobs) = JOBS.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            jobs.remove(&job);
+        }
+    });
+}
+
+/// Compare two screenshot directories, pairing files by relative path.
+/// `request_json` is a `BatchRequest`: `{"baselineDir", "inputDir", "compare": {...}, "threads"}`
+/// where `compare` holds the shared `CompareRequest` settings. Returns the aggregate report
+/// (status, passed/failed/error counts, added and missing screens, per-file results).
+#[no_mangle]
+pub extern "C" fn vt_compare_batch(request_json: *const c_char) -> *const c_char {
+    guard(error_json, || compare_batch_json(request_json))
+}
+
+fn compare_batch_json(request_json: *const c_char) -> String {
+    let Some(_json) = cstr_to_str(request_json) else {
+        return error_json("InvalidArgument", "request_json is null or not valid UTF-8");
+    };
+
+    #[cfg(feature = "real")]
+    let result = match serde_json::from_str::<core_crate::BatchRequest>(_json) {
+        Ok(req) => serde_json::to_string(&core_crate::compare_batch(req)).unwrap_or_else(|_| "{}".to_string()),
+        Err(e) => error_json("InvalidArgument", &format!("invalid batch request: {e}")),
+    };
+
+    #[cfg(feature = "mock")]
+    let result = serde_json::json!({
+        "status": "Passed",
+        "compared": 0,
+        "passed": 0,
+        "failed": 0,
+        "errors": 0,
+        "newBaselines": 0,
+        "unchecked": 0,
+        "added": [],
+        "missing": [],
+        "results": [],
+    })
+    .to_string();
+
+    result
+}
+
+/// Write a self-contained HTML report to `output_path`.
+/// `entries_json` is an array of `{"request": CompareRequest, "result": CompareResult}`,
+/// i.e. each request passed to the SDK with the JSON it returned.
+/// Returns `{"path": ...}`, or `{"error":{code,message}}` with code InvalidArgument or Io.
+#[no_mangle]
+pub extern "C" fn vt_html_report(entries_json: *const c_char, title: *const c_char, output_path: *const c_char) -> *const c_char {
+    guard(error_json, || html_report_json(entries_json, title, output_path))
+}
+
+fn html_report_json(entries_json: *const c_char, title: *const c_char, output_path: *const c_char) -> String {
+    let Some(_entries) = cstr_to_str(entries_json) else {
+        return error_json("InvalidArgument", "entries_json is null or not valid UTF-8");
+    };
+    let Some(_path) = cstr_to_str(output_path) else {
+        return error_json("InvalidArgument", "output_path is null or not valid UTF-8");
+    };
+    let _title = cstr_to_str(title).unwrap_or("Visual test report");
+
+    #[cfg(feature = "real")]
+    let result = match serde_json::from_str::<Vec<core_crate::html_report::ReportEntry>>(_entries) {
+        Ok(entries) => match core_crate::html_report::write(_path, _title, &entries) {
+            Ok(()) => serde_json::json!({ "path": _path }).to_string(),
+            Err(e) => error_json("Io", &format!("failed to write {_path}: {e}")),
+        },
+        Err(e) => error_json("InvalidArgument", &format!("invalid report entries: {e}")),
+    };
+
+    #[cfg(feature = "mock")]
+    let result = serde_json::json!({ "path": _path }).to_string();
+
+    result
+}
+
+/// Approve/reject share everything but the core operation.
+#[cfg(feature = "real")]
+fn review_change(
+    baseline_root: *const c_char,
+    change_id: *const c_char,
+    op: fn(&core_crate::BaselineStore, &str) -> Result<core_crate::PendingChange, core_crate::ApprovalError>,
+) -> String {
+    let Some(root) = cstr_to_str(baseline_root) else { return error_json("InvalidArgument", "baseline_root is null or not valid UTF-8") };
+    let Some(id) = cstr_to_str(change_id) else { return error_json("InvalidArgument", "change_id is null or not valid UTF-8") };
+    match op(&core_crate::BaselineStore::new(root), id) {
+        Ok(change) => serde_json::to_string(&change).unwrap_or_else(|_| "{}".to_string()),
+        Err(e) => serde_json::json!({ "error": e }).to_string(),
+    }
+}
+
+/// List the changes queued under a baseline store. Returns a JSON array.
+/// `state` is `Pending`, `Accepted`, `Rejected`, or null for all.
+#[no_mangle]
+pub extern "C" fn vt_approval_list(baseline_root: *const c_char, state: *const c_char) -> *const c_char {
+    guard(error_json, || approval_list_json(baseline_root, state))
+}
+
+fn approval_list_json(baseline_root: *const c_char, state: *const c_char) -> String {
+    let Some(_root) = cstr_to_str(baseline_root) else {
+        return error_json("InvalidArgument", "baseline_root is null or not valid UTF-8");
+    };
+    let _state = cstr_to_str(state);
+
+    #[cfg(feature = "real")]
+    let result = {
+        let filter = match _state.map(|s| serde_json::from_value(serde_json::Value::String(s.to_string()))) {
+            None => Ok(None),
+            Some(Ok(s)) => Ok(Some(s)),
+            Some(Err(_)) => Err(error_json("InvalidArgument", "state must be Pending, Accepted or Rejected")),
+        };
+        match filter {
+            Ok(filter) => match core_crate::BaselineStore::new(_root).changes(filter) {
+                Ok(changes) => serde_json::to_string(&changes).unwrap_or_else(|_| "[]".to_string()),
+                Err(e) => serde_json::json!({ "error": e }).to_string(),
+            },
+            Err(json) => json,
+        }
+    };
+
+    #[cfg(feature = "mock")]
+    let result = "[]".to_string();
+
+    result
+}
+
+/// Approve a pending change: its candidate replaces the baseline.
+/// Returns the updated change JSON, or `{"error":{code,message}}` with code
+/// NotFound, NotPending, Io or InvalidArgument.
+#[no_mangle]
+pub extern "C" fn vt_approval_approve(baseline_root: *const c_char, change_id: *const c_char) -> *const c_char {
+    guard(error_json, || approval_approve_json(baseline_root, change_id))
+}
+
+fn approval_approve_json(baseline_root: *const c_char, change_id: *const c_char) -> String {
+    #[cfg(feature = "real")]
+    let result = review_change(baseline_root, change_id, core_crate::BaselineStore::approve);
+
+    #[cfg(feature = "mock")]
+    let result = {
+        let _ = (baseline_root, change_id);
+        error_json("NotFound", "mock has no pending changes")
+    };
+
+    result
+}
+
+/// Reject a pending change, keeping the current baseline. Same return shape
+/// as `vt_approval_approve`.
+#[no_mangle]
+pub extern "C" fn vt_approval_reject(baseline_root: *const c_char, change_id: *const c_char) -> *const c_char {
+    guard(error_json, || approval_reject_json(baseline_root, change_id))
+}
+
+fn approval_reject_json(baseline_root: *const c_char, change_id: *const c_char) -> String {
+    #[cfg(feature = "real")]
+    let result = review_change(baseline_root, change_id, core_crate::BaselineStore::reject);
+
+    #[cfg(feature = "mock")]
+    let result = {
+        let _ = (baseline_root, change_id);
+        error_json("NotFound", "mock has no pending changes")
+    };
+
+    result
+}
+
+#[cfg(all(test, feature = "real"))]
+mod tests {
+    use super::*;
+
+    fn call(json: &str) -> serde_json::Value {
+        let input = CString::new(json).unwrap();
+        let out = vt_execute(input.as_ptr());
+        let value = serde_json::from_str(cstr_to_str(out).unwrap()).unwrap();
+        vt_free_string(out);
+        value
+    }
+
+    #[test]
+    fn execute_dispatches_by_operation() {
+        let res = call(r#"{"version":1,"operation":"Compare","request":{"baselineImage":"/nonexistent/a.png","inputImage":"b.png","colorMode":"Rgb"}}"#);
+        assert_eq!(res["operation"], "Compare");
+        assert_eq!(res["result"]["colorMode"], "Rgb");
+        assert_eq!(res["result"]["error"]["code"], "BaselineMissing");
+
+        let res = call(r#"{"version":1,"operation":"Search","request":{"parentImage":"p.png","childImage":"c.png"}}"#);
+        assert_eq!(res["result"]["status"], "Error");
+        assert_eq!(res["result"]["error"]["code"], "InputMissing");
+    }
+
+    #[test]
+    fn execute_rejects_bad_envelopes() {
+        assert_eq!(call(r#"{"version":2,"operation":"Search","request":{}}"#)["error"]["code"], "UnsupportedVersion");
+        assert_eq!(call(r#"{"operation":"Search","request":{}}"#)["error"]["code"], "InvalidRequest");
+        assert_eq!(call(r#"{"version":1,"operation":"Paint","request":{}}"#)["error"]["code"], "InvalidRequest");
+        // Test-only operations are not part of the wire format, even in test builds.
+        assert_eq!(call(r#"{"version":1,"operation":"Panic","request":"boom"}"#)["error"]["code"], "InvalidRequest");
+        assert_eq!(call(r#"{"version":1,"operation":"Search","request":{"parentImage":1}}"#)["error"]["code"], "InvalidRequest");
+        assert_eq!(call("not json")["error"]["code"], "InvalidRequest");
+
+        let out = vt_execute(std::ptr::null());
+        assert!(cstr_to_str(out).unwrap().contains("InvalidRequest"));
+        vt_free_string(out);
+    }
+
+    fn take(out: *const c_char) -> serde_json::Value {
+        let value = serde_json::from_str(cstr_to_str(out).unwrap()).unwrap();
+        vt_free_string(out);
+        value
+    }
+
+    fn last_error() -> Option<serde_json::Value> {
+        let out = vt_last_error();
+        if out.is_null() { None } else { Some(take(out)) }
+    }
+
+    #[test]
+    fn invalid_arguments_return_error_objects() {
+        let missing = CString::new("/nonexistent/vt.png").unwrap();
+        let res = take(vt_flex_search(std::ptr::null(), missing.as_ptr(), std::ptr::null()));
+        assert_eq!(res["error"]["code"], "InvalidArgument");
+        assert_eq!(res["error"]["message"], "parent_url is null or not valid UTF-8");
+
+        let bad_meta = CString::new("{\"testName\": 3").unwrap();
+        let res = take(vt_flex_locate(missing.as_ptr(), missing.as_ptr(), missing.as_ptr(), bad_meta.as_ptr()));
+        assert!(res["error"]["message"].as_str().unwrap().starts_with("meta_json is not valid JSON"));
+
+        let bad_areas = CString::new("[{\"topLeftX\": -1}]").unwrap();
+        let res = take(vt_compare_images(missing.as_ptr(), missing.as_ptr(), 90, -1, bad_areas.as_ptr(), std::ptr::null()));
+        assert_eq!(res["status"], "Error");
+        assert_eq!(res["error"]["code"], "InvalidArgument");
+    }
+
+    #[test]
+    fn last_error_tracks_the_latest_call() {
+        let missing = CString::new("/nonexistent/vt.png").unwrap();
+        let blank = CString::new("  ").unwrap();
+        take(vt_compare_images(missing.as_ptr(), missing.as_ptr(), 90, -1, blank.as_ptr(), blank.as_ptr()));
+        assert_eq!(last_error().unwrap()["code"], "BaselineMissing");
+
+        take(vt_flex_search(missing.as_ptr(), missing.as_ptr(), std::ptr::null()));
+        assert_eq!(last_error().unwrap()["code"], "InputMissing");
+
+        take(vt_approval_approve(std::ptr::null(), std::ptr::null()));
+        assert_eq!(last_error().unwrap()["code"], "InvalidArgument");
+
+        // A call that succeeds clears the last error.
+        take(vt_approval_list(missing.as_ptr(), std::ptr::null()));
+        assert!(last_error().is_none());
+    }
+
+    #[test]
+    fn panics_become_internal_errors() {
+        let res = take(guard(execute_error_json, || execute_with_test_ops(r#"{"version":1,"operation":"Panic","request":"boom"}"#, &CancelFlag::new())));
+        assert_eq!(res["version"], 1);
+        assert_eq!(res["error"]["code"], "Internal");
+        assert_eq!(res["error"]["message"], "internal error: boom");
+        assert_eq!(last_error().unwrap()["code"], "Internal");
+
+        // Non-string payloads and other error shapes.
+        let res = take(guard(compare_error_json, || std::panic::panic_any(42u8)));
+        assert_eq!(res["status"], "Error");
+        assert_eq!(res["error"]["code"], "Internal");
+        assert_eq!(res["error"]["message"], "internal error: panic");
+        let res = take(guard(error_json, || {
+            let v: Vec<u8> = Vec::new();
+            v[3].to_string()
+        }));
+        assert!(res["error"]["message"].as_str().unwrap().contains("index out of bounds"));
+
+        // The library keeps working after a caught panic.
+        let res = call(r#"{"version":1,"operation":"Search","request":{"parentImage":"p.png","childImage":"c.png"}}"#);
+        assert_eq!(res["result"]["error"]["code"], "InputMissing");
+        assert!(last_error().is_none());
+    }
+
+    #[test]
+    fn compares_in_memory_buffers() {
+        let mut png = Vec::new();
+        image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(2, 2, image::Luma([200])))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        let options = CString::new(r#"{"minSimilarity":100,"writeDiff":false}"#).unwrap();
+        let res = take(vt_compare_encoded(png.as_ptr(), png.len(), png.as_ptr(), png.len(), options.as_ptr()));
+        assert_eq!(res["status"], "Passed");
+
+        // Rows padded to 12 bytes; the last row may stop after its pixels.
+        let mut padded = [0u8; 20];
+        for y in 0..2 {
+            padded[y * 12..y * 12 + 8].copy_from_slice(&[200, 200, 200, 255, 200, 200, 200, 255]);
+        }
+        let pixels = [200, 200, 200, 255].repeat(4);
+        let res = take(vt_compare_rgba(padded.as_ptr(), padded.len(), 2, 2, 12, pixels.as_ptr(), pixels.len(), 2, 2, 8, options.as_ptr()));
+        assert_eq!(res["status"], "Passed");
+
+        // Bad geometry is rejected before anything is read.
+        let rgba = |len, width, height, stride| {
+            take(vt_compare_rgba(pixels.as_ptr(), pixels.len(), 2, 2, 8, pixels.as_ptr(), len, width, height, stride, std::ptr::null()))
+        };
+        for (len, width, height, stride) in [(16, 2, 2, 4), (15, 2, 2, 8), (16, 0, 2, 8), (16, u32::MAX, 2, u32::MAX), (16, 2, u32::MAX, 8)] {
+            let res = rgba(len, width, height, stride);
+            assert_eq!(res["error"]["code"], "InvalidArgument", "{width}x{height} stride {stride}: {res}");
+        }
+        assert!(rgba(15, 2, 2, 8)["error"]["message"].as_str().unwrap().contains("needs 16 bytes, got 15"));
+        let res = take(vt_compare_encoded(png.as_ptr(), png.len(), std::ptr::null(), 0, std::ptr::null()));
+        assert_eq!(res["error"]["code"], "InvalidArgument");
+        // Without a baseline buffer or path there is nothing to compare against.
+        let res = take(vt_compare_encoded(std::ptr::null(), 0, png.as_ptr(), png.len(), std::ptr::null()));
+        assert_eq!(res["error"]["code"], "BaselineMissing");
+    }
+
+    fn wait(job: u64) -> serde_json::Value {
+        for _ in 0..500 {
+            let state = take(vt_job_poll(job));
+            if state["state"] != "Running" {
+                return state;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("job {job} did not finish");
+    }
+
+    /// Callback invocations seen by `record`.
+    type Calls = Mutex<Vec<(u64, serde_json::Value)>>;
+
+    extern "C" fn record(job: u64, response: *const c_char, user_data: *mut c_void) {
+        // SAFETY: the tests pass a `Calls` that outlives the job.
+        let calls = unsafe { &*(user_data as *const Calls) };
+        let response = serde_json::from_str(cstr_to_str(response).unwrap()).unwrap();
+        calls.lock().unwrap().push((job, response));
+    }
+
+    fn submit(json: &str) -> u64 {
+        submit_job(&JOB_POOL, Some(json), execute_with_test_ops)
+    }
+
+    static GATES: Mutex<Option<HashMap<String, Arc<std::sync::Barrier>>>> = Mutex::new(None);
+
+    /// Two-party barrier shared by a test and the `Hold`/`Spin` job it runs.
+    fn gate(name: &str) -> Arc<std::sync::Barrier> {
+        let mut gates = GATES.lock().unwrap();
+        let gates = gates.get_or_insert_with(HashMap::new);
+        gates.entry(name.to_string()).or_insert_with(|| Arc::new(std::sync::Barrier::new(2))).clone()
+    }
+
+    /// `execute` plus operations that stand in for core work; their `request`
+    /// is a string:
+    /// - `Panic` panics with it, like a bug deep inside core;
+    /// - `Hold` meets the test at the gate it names when it starts and again
+    ///   before it returns, like a long comparison;
+    /// - `Spin` meets the test at the gate, runs until cancelled, then meets it
+    ///   again, like core work that honours cancellation.
+    fn execute_with_test_ops(request_json: &str, cancel: &CancelFlag) -> String {
+        let envelope: serde_json::Value = serde_json::from_str(request_json).unwrap_or_default();
+        let (Some(operation), Some(name)) = (envelope["operation"].as_str(), envelope["request"].as_str()) else {
+            return execute(request_json, cancel);
+        };
+        match operation {
+            "Panic" => panic!("{name}"),
+            "Hold" => {
+                gate(name).wait();
+                gate(name).wait();
+            }
+            "Spin" => {
+                gate(name).wait();
+                while !cancel.is_cancelled() {
+                    std::thread::yield_now();
+                }
+                gate(name).wait();
+            }
+            _ => return execute(request_json, cancel),
+        }
+        serde_json::json!({ "version": EXECUTE_VERSION, "operation": operation, "result": null }).to_string()
+    }
+
+    #[test]
+    fn jobs_complete_in_the_background() {
+        let calls: Calls = Mutex::new(Vec::new());
+        let user_data = &calls as *const _ as *mut c_void;
+        let job = submit(r#"{"version":1,"operation":"Hold","request":"complete"}"#);
+        assert_ne!(job, 0);
+        assert_eq!(take(vt_job_on_complete(job, Some(record), user_data))["state"], "Running");
+        gate("complete").wait();
+        assert_eq!(take(vt_job_poll(job))["state"], "Running");
+        gate("complete").wait();
+        let state = wait(job);
+        assert_eq!(state["state"], "Completed");
+        assert_eq!(state["response"]["operation"], "Hold");
+
+        let search = submit(r#"{"version":1,"operation":"Search","request":{"parentImage":"p.png","childImage":"c.png"}}"#);
+        assert_eq!(wait(search)["response"]["result"]["status"], "Error");
+        // Registering on a finished job calls back right away.
+        take(vt_job_on_complete(search, Some(record), user_data));
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].0, job);
+        assert_eq!(calls[1].1["result"]["status"], "Error");
+    }
+
+    #[test]
+    fn cancelled_jobs_stop_and_report_cancelled_once() {
+        let calls: Calls = Mutex::new(Vec::new());
+        let job = submit(r#"{"version":1,"operation":"Spin","request":"cancel"}"#);
+        take(vt_job_on_complete(job, Some(record), &calls as *const _ as *mut c_void));
+        gate("cancel").wait();
+        let state = take(vt_job_cancel(job));
+        assert_eq!(state["state"], "Cancelled");
+        assert_eq!(state["response"]["error"]["code"], "Cancelled");
+
+        // The work sees the cancellation and stops; its result does not
+        // overwrite the cancellation.
+        gate("cancel").wait();
+        assert_eq!(take(vt_job_poll(job))["state"], "Cancelled");
+        assert_eq!(calls.lock().unwrap().len(), 1);
+
+        vt_job_free(job);
+        assert_eq!(take(vt_job_poll(job))["error"]["code"], "InvalidArgument");
+        assert_eq!(take(vt_job_cancel(job))["error"]["code"], "InvalidArgument");
+    }
+
+    #[test]
+    fn cancellation_reaches_core() {
+        let cancel = CancelFlag::new();
+        cancel.cancel();
+        let res: serde_json::Value = serde_json::from_str(&execute(
+            r#"{"version":1,"operation":"Compare","request":{"baselineImage":"a.png","inputImage":"b.png"}}"#,
+            &cancel,
+        ))
+        .unwrap();
+        assert_eq!(res["result"]["error"]["code"], "Cancelled");
+    }
+
+    #[test]
+    fn pool_queues_jobs_beyond_its_workers() {
+        let pool: &'static Pool = Box::leak(Box::new(Pool::new(1, spawn_worker)));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for i in 0..3 {
+            let sender = sender.clone();
+            pool.submit(Box::new(move || sender.send(i).unwrap())).unwrap();
+        }
+        let done: Vec<i32> = receiver.iter().take(3).collect();
+        assert_eq!(done, vec![0, 1, 2]);
+        assert_eq!(pool.state.lock().unwrap().workers, 1);
+    }
+
+    #[test]
+    fn jobs_without_a_worker_are_rejected() {
+        fn no_threads(_: Task) -> std::io::Result<()> {
+            Err(std::io::Error::other("thread limit reached"))
+        }
+        let pool: &'static Pool = Box::leak(Box::new(Pool::new(2, no_threads)));
+        assert_eq!(submit_job(pool, Some(r#"{"version":1,"operation":"Search","request":{}}"#), execute), 0);
+        let error = last_error().unwrap();
+        assert_eq!(error["code"], "Internal");
+        assert_eq!(error["message"], "could not start a job worker: thread limit reached");
+    }
+
+    #[test]
+    fn failing_jobs_complete_with_errors() {
+        assert_eq!(wait(submit("not json"))["response"]["error"]["code"], "InvalidRequest");
+        assert_eq!(wait(vt_job_submit(std::ptr::null()))["response"]["error"]["code"], "InvalidRequest");
+        let panicked = wait(submit(r#"{"version":1,"operation":"Panic","request":"boom"}"#));
+        assert_eq!(panicked["state"], "Completed");
+        assert_eq!(panicked["response"]["error"]["code"], "Internal");
+        let job = submit(r#"{"version":1,"operation":"Panic","request":"boom"}"#);
+        assert_eq!(take(vt_job_on_complete(job, None, std::ptr::null_mut()))["error"]["code"], "InvalidArgument");
+    }
 }